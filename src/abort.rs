@@ -0,0 +1,160 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::status::{ABORNOCONN, ABOROK, BADSENDNET};
+
+/// Interpret As Command, starts every Telnet command
+const IAC: u8 = 255;
+/// WILL, WONT, DO and DONT take an option byte after them
+const NEGOTIATION: std::ops::RangeInclusive<u8> = 251..=254;
+/// Telnet commands other than IAC itself are 240 and up
+const COMMAND_MIN: u8 = 240;
+
+/// A control line with its Telnet commands taken out. Clients send ABOR as
+/// `IAC IP IAC DM ABOR` with the DM as urgent data, which leaves a lone IAC
+/// in front of the command when the urgent byte is read out of band.
+/// `IAC IAC` stands for a 255 byte.
+pub fn strip_telnet(line: &[u8]) -> Vec<u8> {
+  let mut out = Vec::with_capacity(line.len());
+  let mut bytes = line.iter().copied().peekable();
+  while let Some(b) = bytes.next() {
+    if b != IAC {
+      out.push(b);
+      continue;
+    }
+    match bytes.peek().copied() {
+      Some(IAC) => {
+        bytes.next();
+        out.push(IAC);
+      }
+      Some(c) if NEGOTIATION.contains(&c) => {
+        bytes.next();
+        bytes.next();
+      }
+      Some(c) if c >= COMMAND_MIN => {
+        bytes.next();
+      }
+      _ => {}
+    }
+  }
+  out
+}
+
+/// The replies to ABOR, in order: `BADSENDNET` for the transfer it cut short
+/// then `ABOROK`, or just `ABORNOCONN` when nothing was being transferred.
+pub fn replies(transferring: bool) -> &'static [u64] {
+  if transferring {
+    &[BADSENDNET, ABOROK]
+  } else {
+    &[ABORNOCONN]
+  }
+}
+
+/// Shared between the thread reading the control channel and the one running
+/// the transfer. `abort` shuts the data socket down, which wakes a transfer
+/// blocked on it, and the transfer fails at its next read or write.
+#[derive(Debug, Clone, Default)]
+pub struct AbortHandle {
+  aborted: Arc<AtomicBool>,
+  /// A clone of the data socket while a transfer runs on it
+  socket: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl AbortHandle {
+  pub fn new() -> AbortHandle {
+    AbortHandle::default()
+  }
+
+  pub fn abort(&self) {
+    self.aborted.store(true, Ordering::SeqCst);
+    if let Some(ref socket) = *self.socket.lock().unwrap() {
+      // Already closed by the client is as good as shut down
+      let _ = socket.shutdown(Shutdown::Both);
+    }
+  }
+
+  pub fn is_aborted(&self) -> bool {
+    self.aborted.load(Ordering::SeqCst)
+  }
+
+  /// Wraps the data connection so it fails with `ConnectionAborted` after `abort`
+  pub fn wrap<T>(&self, inner: T) -> Abortable<T> {
+    Abortable {
+      inner: Some(inner),
+      handle: self.clone(),
+    }
+  }
+
+  /// Wraps a data socket, keeping a clone of it so `abort` can shut it down.
+  /// The clone is let go when the wrapper is dropped or unwrapped, so the
+  /// socket closes as usual once the transfer is done with it.
+  pub fn wrap_socket(&self, socket: TcpStream) -> io::Result<Abortable<TcpStream>> {
+    *self.socket.lock().unwrap() = Some(socket.try_clone()?);
+    if self.is_aborted() {
+      let _ = socket.shutdown(Shutdown::Both);
+    }
+    Ok(self.wrap(socket))
+  }
+
+  fn check(&self) -> io::Result<()> {
+    if self.is_aborted() {
+      Err(io::Error::new(io::ErrorKind::ConnectionAborted, "transfer aborted"))
+    } else {
+      Ok(())
+    }
+  }
+}
+
+pub struct Abortable<T> {
+  /// Only taken by `into_inner`
+  inner: Option<T>,
+  handle: AbortHandle,
+}
+
+impl<T> Abortable<T> {
+  pub fn into_inner(mut self) -> T {
+    self
+      .inner
+      .take()
+      .expect("the inner value is only taken here")
+  }
+
+  fn inner(&mut self) -> &mut T {
+    self
+      .inner
+      .as_mut()
+      .expect("the inner value is only taken by into_inner")
+  }
+}
+
+impl<T> Drop for Abortable<T> {
+  fn drop(&mut self) {
+    self.handle.socket.lock().unwrap().take();
+  }
+}
+
+impl<R: Read> Read for Abortable<R> {
+  fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+    self.handle.check()?;
+    let read = self.inner().read(out);
+    // A read woken by the shutdown in `abort` ends in EOF or an error
+    self.handle.check()?;
+    read
+  }
+}
+
+impl<W: Write> Write for Abortable<W> {
+  fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+    self.handle.check()?;
+    let written = self.inner().write(data);
+    self.handle.check()?;
+    written
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.handle.check()?;
+    self.inner().flush()
+  }
+}
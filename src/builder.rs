@@ -0,0 +1,170 @@
+use std::env;
+use std::fmt::{self, Display, Formatter};
+
+use serde::Deserialize;
+
+use super::conf::{self, Strictness};
+use super::config::{Config, Overrides};
+use super::err::FtpdError;
+use super::types::IpNet;
+
+/// Where the final value of a key came from
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Source {
+  /// `defaults.rs`
+  Default,
+  /// The file and line that set it, an included file names itself
+  File { path: String, line: usize },
+  /// The `FTPD_<KEY>` variable that set it
+  Env(String),
+  /// Set on the `ConfigBuilder`
+  Override,
+}
+
+impl Display for Source {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Source::Default => write!(f, "default"),
+      Source::File { path, line } => write!(f, "{}:{}", path, line),
+      Source::Env(var) => write!(f, "environment {}", var),
+      Source::Override => write!(f, "override"),
+    }
+  }
+}
+
+/// The source of every top level key, in `Config::KEYS` order
+#[derive(Debug, Clone)]
+pub struct Provenance(Vec<(&'static str, Source)>);
+
+impl Provenance {
+  pub fn get(&self, key: &str) -> Option<&Source> {
+    self
+      .0
+      .iter()
+      .find(|(k, _)| *k == key)
+      .map(|(_, source)| source)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&'static str, &Source)> {
+    self.0.iter().map(|(key, source)| (*key, source))
+  }
+
+  fn set(&mut self, key: &str, source: Source) {
+    if let Some(entry) = self.0.iter_mut().find(|(k, _)| *k == key) {
+      entry.1 = source;
+    }
+  }
+}
+
+/// Merges config layers from lowest to highest precedence: the defaults, the
+/// files in the order they were added, the `FTPD_` environment when asked for,
+/// then the values set on the builder itself. A file replaces only the keys it sets and the
+/// `[user:...]` and `[ip:...]` sections of the same name.
+#[derive(Default)]
+pub struct ConfigBuilder {
+  files: Vec<String>,
+  env: bool,
+  overrides: Vec<(String, Override)>,
+}
+
+type Override = Box<dyn FnOnce(&mut Config) -> Result<(), conf::Error>>;
+
+impl ConfigBuilder {
+  pub fn new() -> ConfigBuilder {
+    ConfigBuilder::default()
+  }
+
+  pub fn file(mut self, path: &str) -> ConfigBuilder {
+    self.files.push(path.into());
+    self
+  }
+
+  pub fn env(mut self) -> ConfigBuilder {
+    self.env = true;
+    self
+  }
+
+  /// Sets one top level key from its text, as if it were written `key = value`
+  pub fn set(mut self, key: &str, value: &str) -> ConfigBuilder {
+    let (name, value) = (key.to_string(), value.to_string());
+    let set = move |config: &mut Config| {
+      config
+        .set(&name, &value)
+        .map_err(|e| conf::Error::Custom(format!("{}: {}", name, e)))
+    };
+    self.overrides.push((key.into(), Box::new(set)));
+    self
+  }
+
+  /// Adds or replaces a `[user:name]` section
+  pub fn user(self, name: &str, overrides: Overrides) -> ConfigBuilder {
+    let name = name.to_string();
+    self.with("user", move |config| {
+      config.user.insert(name, overrides);
+    })
+  }
+
+  /// Adds or replaces an `[ip:net]` section
+  pub fn ip(self, net: IpNet, overrides: Overrides) -> ConfigBuilder {
+    self.with("ip", move |config| {
+      config.ip.insert(net, overrides);
+    })
+  }
+
+  pub(crate) fn with<F: FnOnce(&mut Config) + 'static>(mut self, key: &str, f: F) -> ConfigBuilder {
+    let set = move |config: &mut Config| {
+      f(config);
+      Ok(())
+    };
+    self.overrides.push((key.into(), Box::new(set)));
+    self
+  }
+
+  pub fn build(self) -> Result<Config, FtpdError> {
+    self.build_with_provenance().map(|(config, _)| config)
+  }
+
+  /// The validated config and where each of its keys came from
+  pub fn build_with_provenance(self) -> Result<(Config, Provenance), FtpdError> {
+    let mut config = Config::default();
+    let mut provenance = Provenance(Config::KEYS.iter().map(|&k| (k, Source::Default)).collect());
+
+    for path in &self.files {
+      let mut de = Config::open(path)?.strictness(Strictness::Strict);
+      let layer = Config::deserialize(&mut de)?;
+      de.assert_eof()?;
+      for (key, pos) in de.positions() {
+        config.copy_key(&layer, key);
+        let path = pos.file.clone().unwrap_or_else(|| path.clone());
+        provenance.set(
+          key,
+          Source::File {
+            path,
+            line: pos.line,
+          },
+        );
+      }
+      config.user.extend(layer.user);
+      config.ip.extend(layer.ip);
+    }
+
+    if self.env {
+      for (var, value) in env::vars() {
+        if let Some(key) = Config::env_key(&var) {
+          config
+            .set(key, &value)
+            .map_err(|e| conf::Error::Custom(format!("{}: {}", var, e)))?;
+          provenance.set(key, Source::Env(var));
+        }
+      }
+    }
+
+    for (key, set) in self.overrides {
+      set(&mut config)?;
+      provenance.set(&key, Source::Override);
+    }
+
+    config.validate()?;
+    Ok((config, provenance))
+  }
+}
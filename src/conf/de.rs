@@ -1,15 +1,19 @@
 #![allow(dead_code)]
-use super::parse::{self, Item};
+use super::parse::{self, Item, Position};
 use serde::de::{
-  self, Deserialize, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor,
+  self, Deserialize, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess,
+  SeqAccess, VariantAccess, Visitor,
 };
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Formatter;
-use std::mem::replace;
+use std::marker::PhantomData;
+use std::mem::{replace, take};
+use std::path::Path;
 use std::str::FromStr;
 use std::{
-  error, fmt, io,
+  error, fmt, io, iter,
   num::{ParseFloatError, ParseIntError},
-  result,
+  result, str,
 };
 
 pub trait NextExt {
@@ -28,8 +32,73 @@ where
 #[derive(Debug, Clone)]
 pub enum Error {
   Custom(String),
+  /// A struct field with no key and no default
+  MissingField(&'static str),
   UnexpectedEOF,
   InvalidState,
+  /// An error tied to the line (and key) it came from
+  Located {
+    pos: Box<Position>,
+    key: Option<String>,
+    inner: Box<Error>,
+  },
+  /// An error about the file as a whole, such as a key missing from it
+  TopLevel {
+    file: Option<String>,
+    inner: Box<Error>,
+  },
+}
+
+impl Error {
+  fn at(self, key: Option<&str>, pos: &Position) -> Self {
+    match self {
+      e @ Error::Located { .. } => e,
+      e => Error::Located {
+        pos: Box::new(pos.clone()),
+        key: key.map(Into::into),
+        inner: Box::new(e),
+      },
+    }
+  }
+
+  /// Places an error about a whole section or dotted struct, such as a key
+  /// missing from it, on the line it starts on
+  fn within(self, path: &str, start: &Position) -> Self {
+    match self {
+      e @ Error::MissingField(_) => Error::Custom(format!("{} in `{}`", e, path)).at(None, start),
+      e => e,
+    }
+  }
+
+  /// Names the file the input came from, unless the error already has one.
+  pub fn with_file(mut self, file: &str) -> Self {
+    match self {
+      Error::Located { ref mut pos, .. } => {
+        pos.file.get_or_insert_with(|| file.into());
+      }
+      Error::TopLevel {
+        file: ref mut f, ..
+      } => {
+        f.get_or_insert_with(|| file.into());
+      }
+      _ => {}
+    }
+    self
+  }
+
+  pub fn position(&self) -> Option<&Position> {
+    match self {
+      Error::Located { pos, .. } => Some(pos),
+      _ => None,
+    }
+  }
+
+  pub fn key(&self) -> Option<&str> {
+    match self {
+      Error::Located { key, .. } => key.as_deref(),
+      _ => None,
+    }
+  }
 }
 
 impl From<ParseIntError> for Error {
@@ -46,7 +115,12 @@ impl From<ParseFloatError> for Error {
 
 impl<E: error::Error> From<parse::Error<E>> for Error {
   fn from(e: parse::Error<E>) -> Self {
-    Error::Custom(e.to_string())
+    match e {
+      parse::Error::Syntax(_, ref pos) | parse::Error::Include(_, ref pos) => {
+        Error::Custom(e.to_string()).at(None, pos)
+      }
+      parse::Error::Inner(e) => Error::Custom(e.to_string()),
+    }
   }
 }
 
@@ -54,8 +128,23 @@ impl fmt::Display for Error {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     match self {
       Error::Custom(msg) => write!(f, "{}", msg),
+      Error::MissingField(field) => write!(f, "missing field `{}`", field),
       Error::UnexpectedEOF => write!(f, "unexpected EOF"),
       Error::InvalidState => write!(f, "invalid state error"),
+      Error::Located { pos, key, inner } => {
+        match pos.file {
+          Some(ref file) => write!(f, "{}:{}:{}: {}", file, pos.line, pos.column, inner)?,
+          None => write!(f, "line {}, column {}: {}", pos.line, pos.column, inner)?,
+        }
+        if let Some(key) = key {
+          write!(f, " (key `{}`)", key)?;
+        }
+        write!(f, "\n{}", pos)
+      }
+      Error::TopLevel { file, inner } => match file {
+        Some(file) => write!(f, "{}: {} at the top level", file, inner),
+        None => write!(f, "{} at the top level", inner),
+      },
     }
   }
 }
@@ -69,18 +158,33 @@ impl de::Error for Error {
   {
     Error::Custom(msg.to_string())
   }
+
+  fn missing_field(field: &'static str) -> Self {
+    Error::MissingField(field)
+  }
 }
 
 type Result<T> = result::Result<T, Error>;
 
+/// How the top level keys are checked against the target struct
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Strictness {
+  /// Unknown keys and repeated keys are errors
+  Strict,
+  /// Unknown keys are ignored, as before
+  #[default]
+  Lenient,
+}
+
 enum PeekKind {
   Value,
+  Section,
 }
 
 #[derive(Debug, Copy, Clone)]
 enum Next<T> {
   Init,
-  EOF,
+  Eof,
   Some(T),
 }
 
@@ -88,6 +192,59 @@ enum Next<T> {
 pub struct Deserializer<T> {
   input: T,
   next: Next<Result<Item>>,
+  current: Option<(String, Position)>,
+  strictness: Strictness,
+  keys: Keys,
+  /// The file read, named by errors that have no line of their own
+  file: Option<String>,
+  /// Lines set aside while a group of sections was gathered, read again
+  /// before the rest of the input
+  replay: VecDeque<Result<Item>>,
+}
+
+/// The keys of one map, checked in strict mode
+#[derive(Debug, Default)]
+struct Keys {
+  fields: Option<&'static [&'static str]>,
+  seen: HashMap<String, Position>,
+}
+
+impl Keys {
+  /// The dotted keys under one name have to come as a single run in strict mode
+  fn check_run(&self, strictness: Strictness, name: &str, what: &str) -> Result<()> {
+    match self.seen.get(name) {
+      Some(first) if strictness == Strictness::Strict => Err(Error::Custom(format!(
+        "{} must be kept together, the first one is on line {}",
+        what, first.line
+      ))),
+      _ => Ok(()),
+    }
+  }
+
+  fn check(&mut self, strictness: Strictness, key: &str, pos: Position) -> Result<()> {
+    if strictness == Strictness::Lenient {
+      return Ok(());
+    }
+    if let Some(fields) = self.fields {
+      if !fields.contains(&key) {
+        return Err(Error::Custom(match suggest(key, fields) {
+          Some(field) => format!("unknown key `{}`, did you mean `{}`?", key, field),
+          None => format!("unknown key `{}`", key),
+        }));
+      }
+    }
+    // The line text is only needed while the error is being reported
+    let pos = Position {
+      text: String::new(),
+      ..pos
+    };
+    match self.seen.insert(key.into(), pos) {
+      Some(first) => {
+        Err(Error::Custom(format!("duplicate key `{}`, first set on line {}", key, first.line)))
+      }
+      None => Ok(()),
+    }
+  }
 }
 
 impl<T> Deserializer<T> {
@@ -95,6 +252,25 @@ impl<T> Deserializer<T> {
     Deserializer {
       input,
       next: Next::Init,
+      current: None,
+      strictness: Strictness::default(),
+      keys: Keys::default(),
+      file: None,
+      replay: VecDeque::new(),
+    }
+  }
+
+  pub fn strictness(mut self, strictness: Strictness) -> Self {
+    self.strictness = strictness;
+    self
+  }
+
+  /// Ties an error to the key being deserialized, if any.
+  fn locate(&self, e: Error, key_span: bool) -> Error {
+    match self.current {
+      Some((ref key, ref pos)) if key_span => e.at(Some(key), &pos.key_span()),
+      Some((ref key, ref pos)) => e.at(Some(key), pos),
+      None => e,
     }
   }
 }
@@ -102,25 +278,69 @@ impl<T> Deserializer<T> {
 impl<T: NextExt> Deserializer<T> {
   fn populate(&mut self) {
     while let Next::Init = self.next {
-      let next = self.input.next();
-      self.next = match next {
-        Some(Ok(Item::Comment { .. })) => Next::Init,
-        Some(Ok(Item::Empty)) => Next::Init,
+      self.next = match self.pull() {
         Some(v) => Next::Some(v),
-        None => Next::EOF,
+        None => Next::Eof,
       };
     }
   }
 
+  /// The next item that is not a comment or empty line, set aside ones first
+  fn pull(&mut self) -> Option<Result<Item>> {
+    loop {
+      match self.replay.pop_front().or_else(|| self.input.next()) {
+        Some(Ok(Item::Comment { .. })) | Some(Ok(Item::Empty)) => {}
+        next => return next,
+      }
+    }
+  }
+
+  /// Moves the item up next and those after it to `aside` until the header of
+  /// a `[kind:name]` section, which is left up next. False at the end of the
+  /// input or an error, with everything read moved to `aside`.
+  fn skip_to_group(&mut self, kind: &str, aside: &mut Vec<Result<Item>>) -> bool {
+    self.populate();
+    if let Next::Some(next) = replace(&mut self.next, Next::Init) {
+      aside.push(next);
+    }
+    while let Some(next) = self.pull() {
+      match next {
+        Ok(Item::Section { ref name, .. }) if group_kind(name) == Some(kind) => {
+          self.next = Next::Some(next);
+          return true;
+        }
+        Ok(item) => aside.push(Ok(item)),
+        Err(e) => {
+          aside.push(Err(e));
+          break;
+        }
+      }
+    }
+    false
+  }
+
+  /// Puts the items set aside back in front of the rest of the input
+  fn restore(&mut self, aside: Vec<Result<Item>>) {
+    if aside.is_empty() {
+      return;
+    }
+    if let Next::Some(next) = replace(&mut self.next, Next::Init) {
+      self.replay.push_front(next);
+    }
+    for item in aside.into_iter().rev() {
+      self.replay.push_front(item);
+    }
+  }
+
   fn next_item(&mut self) -> Result<Item> {
     let next = match self.next {
-      Next::EOF | Next::Some(Err(..)) => Next::EOF,
+      Next::Eof | Next::Some(Err(..)) => Next::Eof,
       _ => Next::Init,
     };
     let next = replace(&mut self.next, next);
     match next {
       Next::Some(v) => v,
-      Next::EOF => Err(Error::UnexpectedEOF),
+      Next::Eof => Err(Error::UnexpectedEOF),
       Next::Init => unreachable!(),
     }
   }
@@ -129,13 +349,13 @@ impl<T: NextExt> Deserializer<T> {
     match &mut self.next {
       &mut Next::Some(Ok(ref mut v)) => Ok(Some(v)),
       e @ &mut Next::Some(Err(..)) => {
-        if let Next::Some(Err(e)) = replace(e, Next::EOF) {
+        if let Next::Some(Err(e)) = replace(e, Next::Eof) {
           Err(e)
         } else {
           unreachable!()
         }
       }
-      &mut Next::EOF => Ok(None),
+      &mut Next::Eof => Ok(None),
       &mut Next::Init => unreachable!(),
     }
   }
@@ -144,6 +364,7 @@ impl<T: NextExt> Deserializer<T> {
     self.populate();
     Ok(match self.peek_item()? {
       Some(&mut Item::Value { .. }) => Some(PeekKind::Value),
+      Some(&mut Item::Section { .. }) => Some(PeekKind::Section),
       None => None,
       Some(..) => unreachable!(),
     })
@@ -152,12 +373,124 @@ impl<T: NextExt> Deserializer<T> {
   fn next_key(&mut self) -> Result<String> {
     self.populate();
     match self.peek_item()? {
-      Some(&mut Item::Value { ref mut key, .. }) => Ok(replace(key, "".to_string())),
+      Some(&mut Item::Value {
+        ref mut key,
+        ref pos,
+        ..
+      }) => {
+        let key = replace(key, "".to_string());
+        self.current = Some((key.clone(), pos.clone()));
+        Ok(key)
+      }
       Some(..) => Err(Error::InvalidState),
       None => Err(Error::UnexpectedEOF),
     }
   }
 
+  /// The key up next, without consuming it
+  fn peek_key(&mut self) -> Result<Option<String>> {
+    self.populate();
+    match self.peek_item()? {
+      Some(&mut Item::Value {
+        ref key, ref pos, ..
+      }) => {
+        let key = key.clone();
+        let pos = pos.clone();
+        self.current = Some((key.clone(), pos));
+        Ok(Some(key))
+      }
+      _ => Ok(None),
+    }
+  }
+
+  /// The next field of the map at `prefix`, the empty prefix for the top level
+  /// or a section. A plain `field = value` is consumed up to the value. With
+  /// `split`, for a struct or enum, a dotted `field.rest = value` is consumed
+  /// no further and the path to the nested map, `prefix.field`, comes with it.
+  /// Without it the whole `field.rest` is one key, as a map's keys may hold dots.
+  fn next_field(&mut self, prefix: &str, split: bool) -> Result<Option<(String, Option<String>)>> {
+    let key = match self.peek_key()? {
+      Some(key) => key,
+      None => return Ok(None),
+    };
+    let rest = match prefix {
+      "" => &*key,
+      prefix => match key
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix('.'))
+      {
+        Some(rest) => rest,
+        None => return Ok(None),
+      },
+    };
+    match rest.find('.').filter(|_| split) {
+      Some(i) => {
+        let field = rest[..i].trim().to_string();
+        let path = match prefix {
+          "" => field.clone(),
+          prefix => format!("{}.{}", prefix, field),
+        };
+        Ok(Some((field, Some(path))))
+      }
+      None => {
+        let field = rest.trim().to_string();
+        self.next_key()?;
+        Ok(Some((field, None)))
+      }
+    }
+  }
+
+  /// The name of the section header up next, without consuming it
+  fn peek_section(&mut self) -> Result<Option<String>> {
+    self.populate();
+    match self.peek_item()? {
+      Some(&mut Item::Section { ref name, ref pos }) => {
+        let name = name.clone();
+        let pos = pos.clone();
+        self.current = Some((name.clone(), pos));
+        Ok(Some(name))
+      }
+      _ => Ok(None),
+    }
+  }
+
+  fn next_section(&mut self) -> Result<String> {
+    self.populate();
+    match self.next_item()? {
+      Item::Section { name, pos } => {
+        self.current = Some((name.clone(), pos));
+        Ok(name)
+      }
+      _ => Err(Error::InvalidState),
+    }
+  }
+
+  fn position(&self) -> Position {
+    self
+      .current
+      .as_ref()
+      .map_or_else(Position::default, |(_, pos)| pos.clone())
+  }
+
+  /// Deserializes a whole section or dotted struct with `f`, placing errors
+  /// that have no line of their own on the line it starts on
+  fn within<R>(&mut self, path: &str, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+    let start = self.position();
+    f(self).map_err(|e| e.within(path, &start))
+  }
+
+  /// Reads the `[name]` sections one at a time, for files too large to hold
+  /// as a single value such as a user database. Only the section being read is
+  /// in memory, a value outside any section is an error.
+  pub fn sections<V: DeserializeOwned>(&mut self) -> Sections<'_, T, V> {
+    Sections(self, PhantomData)
+  }
+
+  /// Where each top level key was set, once deserialized in strict mode
+  pub fn positions(&self) -> impl Iterator<Item = (&str, &Position)> {
+    self.keys.seen.iter().map(|(key, pos)| (&**key, pos))
+  }
+
   fn next_value(&mut self) -> Result<String> {
     self.populate();
     match self.next_item()? {
@@ -166,7 +499,37 @@ impl<T: NextExt> Deserializer<T> {
     }
   }
 
-  fn assert_eof(&mut self) -> Result<()> {
+  /// The elements of the next value as a list, a quoted value is a single element
+  fn next_list(&mut self) -> Result<Vec<String>> {
+    self.populate();
+    let quoted = matches!(self.peek_item()?, Some(&mut Item::Value { quoted: true, .. }));
+    let value = self.next_value()?;
+    if quoted {
+      Ok(vec![value])
+    } else {
+      split_list(&value)
+    }
+  }
+
+  /// The list elements of the next item if it repeats the current key
+  fn next_repeat(&mut self) -> Result<Option<Vec<String>>> {
+    self.populate();
+    let current = self.current.as_ref().map(|(key, _)| key.clone());
+    let repeats = match self.peek_item()? {
+      Some(&mut Item::Value { ref key, .. }) => Some(key) == current.as_ref(),
+      _ => false,
+    };
+    if !repeats {
+      return Ok(None);
+    }
+    if let Some(&mut Item::Value { ref pos, .. }) = self.peek_item()? {
+      let pos = pos.clone();
+      self.current = current.map(|key| (key, pos));
+    }
+    self.next_list().map(Some)
+  }
+
+  pub fn assert_eof(&mut self) -> Result<()> {
     self.populate();
     match self.peek_item()? {
       Some(..) => Err(Error::InvalidState),
@@ -175,32 +538,72 @@ impl<T: NextExt> Deserializer<T> {
   }
 }
 
-impl<'de, 'a, T: NextExt> de::Deserializer<'de> for &'a mut Deserializer<T> {
+impl<'de, T: NextExt> de::Deserializer<'de> for &mut Deserializer<T> {
   type Error = Error;
 
   fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-    visitor.visit_map(MapAccessTop(self))
+    visitor
+      .visit_map(MapAccessTop {
+        de: &mut *self,
+        entry: Entry::Value,
+      })
+      .map_err(|e| match self.locate(e, true) {
+        e @ Error::Located { .. } => e,
+        e => Error::TopLevel {
+          file: self.file.clone(),
+          inner: Box::new(e),
+        },
+      })
   }
 
   fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
     visitor.visit_some(self)
   }
 
+  fn deserialize_struct<V>(
+    self,
+    _name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value>
+  where
+    V: Visitor<'de>,
+  {
+    self.keys.fields = Some(fields);
+    self.deserialize_any(visitor)
+  }
+
   forward_to_deserialize_any! {
     bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
     byte_buf unit unit_struct newtype_struct seq tuple tuple_struct
-    map struct identifier ignored_any enum
+    map identifier ignored_any enum
+  }
+}
+
+impl<'a> Deserializer<parse::Parser<parse::OkIter<str::Lines<'a>>>> {
+  #[allow(clippy::should_implement_trait)]
+  pub fn from_str(s: &'a str) -> Self {
+    Deserializer::new(parse::Parser::from_str(s))
+  }
+}
+
+impl Deserializer<parse::FileParser> {
+  /// Reads the file at `path`, following its `include` lines
+  pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    let mut de = Deserializer::new(parse::FileParser::open(&path)?);
+    de.file = Some(path.as_ref().display().to_string());
+    Ok(de)
   }
 }
 
 impl<R: io::BufRead> Deserializer<parse::Parser<io::Lines<R>>> {
-  fn from_buf_read(reader: R) -> Self {
+  pub fn from_buf_read(reader: R) -> Self {
     Deserializer::new(parse::Parser::from_buf_read(reader))
   }
 }
 
 impl<R: io::Read> Deserializer<parse::Parser<io::Lines<io::BufReader<R>>>> {
-  fn from_read(reader: R) -> Self {
+  pub fn from_read(reader: R) -> Self {
     Deserializer::new(parse::Parser::from_read(reader))
   }
 }
@@ -213,21 +616,26 @@ impl<'de, 'a, T: NextExt> de::Deserializer<'de> for &'a mut ValueDeserializer<'a
   fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
     match self.0.peek_kind()? {
       Some(PeekKind::Value) => self.deserialize_str(visitor),
-      None => Err(Error::InvalidState),
+      _ => Err(Error::InvalidState),
     }
   }
 
   fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
     match self.0.peek_kind()? {
       Some(PeekKind::Value) => {
-        let value = self.0.next_value()?.to_lowercase();
-        visitor.visit_bool(match &*value {
-          "true" | "yes" | "1" => true,
-          "false" | "no" | "0" => false,
-          _ => false,
+        let value = self.0.next_value()?;
+        visitor.visit_bool(match &*value.to_lowercase() {
+          "true" | "yes" | "on" | "1" => true,
+          "false" | "no" | "off" | "0" => false,
+          _ => {
+            return Err(Error::Custom(format!(
+              "invalid boolean `{}`, expected one of true, false, yes, no, on, off, 1, 0",
+              value
+            )))
+          }
         })
       }
-      None => Err(Error::InvalidState),
+      _ => Err(Error::InvalidState),
     }
   }
 
@@ -327,11 +735,12 @@ impl<'de, 'a, T: NextExt> de::Deserializer<'de> for &'a mut ValueDeserializer<'a
   }
 
   fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-    self.deserialize_any(visitor)
+    let values = self.0.next_list()?;
+    visitor.visit_seq(SeqAccessList(&mut *self.0, values.into_iter()))
   }
 
   fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
-    self.deserialize_any(visitor)
+    self.deserialize_seq(visitor)
   }
 
   fn deserialize_tuple_struct<V: Visitor<'de>>(
@@ -369,7 +778,7 @@ impl<'de, 'a, T: NextExt> de::Deserializer<'de> for &'a mut ValueDeserializer<'a
     V: Visitor<'de>,
   {
     match self.0.peek_kind()? {
-      Some(PeekKind::Value) => visitor.visit_enum(self.0.next_value()?.into_deserializer()),
+      Some(PeekKind::Value) => visitor.visit_enum(ValueEnum::parse(self.0.next_value()?)?),
       _ => Err(Error::InvalidState),
     }
   }
@@ -383,7 +792,23 @@ impl<'de, 'a, T: NextExt> de::Deserializer<'de> for &'a mut ValueDeserializer<'a
   }
 }
 
-struct MapAccessTop<'a, T: NextExt + 'a>(&'a mut Deserializer<T>);
+/// The top level map: plain values, then one entry per `[name]` section and one
+/// per kind of `[kind:name]` sections, which map to `kind = { name = {...}, ... }`
+struct MapAccessTop<'a, T: NextExt + 'a> {
+  de: &'a mut Deserializer<T>,
+  entry: Entry,
+}
+
+/// What the key just read is followed by
+enum Entry {
+  Value,
+  /// `key.field = value` lines below the path
+  Dotted(String),
+  /// `[name]`
+  Section,
+  /// `[kind:name]` sections of the kind
+  Group(String),
+}
 
 impl<'de, 'a, T: NextExt + 'a> MapAccess<'de> for MapAccessTop<'a, T> {
   type Error = Error;
@@ -392,26 +817,605 @@ impl<'de, 'a, T: NextExt + 'a> MapAccess<'de> for MapAccessTop<'a, T> {
   where
     K: DeserializeSeed<'de>,
   {
-    match self.0.peek_kind()? {
-      Some(PeekKind::Value) => seed.deserialize(self.0.next_key()?.into_deserializer()),
+    let strictness = self.de.strictness;
+    let key = match self.de.peek_kind()? {
+      Some(PeekKind::Value) => match self.de.next_field("", self.de.keys.fields.is_some())? {
+        Some((key, Some(path))) => {
+          let what = format!("`{}.*` keys", path);
+          self.de.keys.check_run(strictness, &key, &what)?;
+          self.entry = Entry::Dotted(path);
+          key
+        }
+        Some((key, None)) => {
+          self.entry = Entry::Value;
+          key
+        }
+        None => return Err(Error::InvalidState),
+      },
+      Some(PeekKind::Section) => {
+        let name = self.de.peek_section()?.unwrap_or_default();
+        match name.find(':') {
+          Some(i) => {
+            let kind = name[..i].trim().to_string();
+            self.entry = Entry::Group(kind.clone());
+            kind
+          }
+          None => {
+            self.entry = Entry::Section;
+            self.de.next_section()?
+          }
+        }
+      }
+      None => {
+        self.de.current = None;
+        return Ok(None);
+      }
+    };
+    let pos = self.de.position();
+    self.de.keys.check(strictness, &key, pos)?;
+    seed.deserialize(key.into_deserializer()).map(Some)
+  }
+
+  fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+  where
+    V: DeserializeSeed<'de>,
+  {
+    match replace(&mut self.entry, Entry::Value) {
+      Entry::Value => seed
+        .deserialize(&mut ValueDeserializer(&mut *self.de))
+        .map_err(|e| self.de.locate(e, false)),
+      Entry::Dotted(path) => seed.deserialize(&mut DottedDeserializer(&mut *self.de, path)),
+      Entry::Section => seed.deserialize(&mut SectionDeserializer(&mut *self.de)),
+      Entry::Group(kind) => seed.deserialize(&mut GroupDeserializer(&mut *self.de, kind)),
+    }
+  }
+}
+
+/// The values of one section
+struct SectionDeserializer<'a, T: 'a>(&'a mut Deserializer<T>);
+
+impl<'a, T: NextExt> SectionDeserializer<'a, T> {
+  /// `[name]` of the section just read
+  fn path(&self) -> String {
+    let name = self.0.current.as_ref().map_or("", |(name, _)| name);
+    format!("[{}]", name)
+  }
+}
+
+impl<'de, 'a, T: NextExt> de::Deserializer<'de> for &'a mut SectionDeserializer<'a, T> {
+  type Error = Error;
+
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+    let path = self.path();
+    self
+      .0
+      .within(&path, |de| visitor.visit_map(MapAccessFields::new(de, String::new(), None)))
+  }
+
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+    visitor.visit_some(self)
+  }
+
+  fn deserialize_struct<V>(
+    self,
+    _name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value>
+  where
+    V: Visitor<'de>,
+  {
+    let path = self.path();
+    self
+      .0
+      .within(&path, |de| visitor.visit_map(MapAccessFields::new(de, String::new(), Some(fields))))
+  }
+
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+    byte_buf unit unit_struct newtype_struct seq tuple tuple_struct
+    map identifier ignored_any enum
+  }
+}
+
+/// The values of a section, or the dotted keys below a path
+struct MapAccessFields<'a, T: NextExt + 'a> {
+  de: &'a mut Deserializer<T>,
+  prefix: String,
+  keys: Keys,
+  nested: Option<String>,
+}
+
+impl<'a, T: NextExt + 'a> MapAccessFields<'a, T> {
+  fn new(
+    de: &'a mut Deserializer<T>,
+    prefix: String,
+    fields: Option<&'static [&'static str]>,
+  ) -> Self {
+    let keys = Keys {
+      fields,
+      ..Keys::default()
+    };
+    MapAccessFields {
+      de,
+      prefix,
+      keys,
+      nested: None,
+    }
+  }
+}
+
+impl<'de, 'a, T: NextExt + 'a> MapAccess<'de> for MapAccessFields<'a, T> {
+  type Error = Error;
+
+  fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+  where
+    K: DeserializeSeed<'de>,
+  {
+    let strictness = self.de.strictness;
+    let split = self.keys.fields.is_some();
+    let (key, nested) = match self.de.next_field(&self.prefix, split)? {
+      Some(field) => field,
       None => return Ok(None),
+    };
+    if let Some(ref path) = nested {
+      self
+        .keys
+        .check_run(strictness, &key, &format!("`{}.*` keys", path))?;
     }
-    .map(Some)
+    let pos = self.de.position();
+    self.keys.check(strictness, &key, pos)?;
+    self.nested = nested;
+    seed.deserialize(key.into_deserializer()).map(Some)
   }
 
   fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
   where
     V: DeserializeSeed<'de>,
   {
-    match self.0.peek_kind()? {
-      Some(PeekKind::Value) => seed.deserialize(&mut ValueDeserializer(self.0)),
-      None => Err(Error::UnexpectedEOF),
+    match self.nested.take() {
+      Some(path) => seed.deserialize(&mut DottedDeserializer(&mut *self.de, path)),
+      None => seed
+        .deserialize(&mut ValueDeserializer(&mut *self.de))
+        .map_err(|e| self.de.locate(e, false)),
     }
   }
 }
 
+/// The `path.field = value` lines below a path, as a struct, a map or an enum
+/// whose variant is the next part of the key
+struct DottedDeserializer<'a, T: 'a>(&'a mut Deserializer<T>, String);
+
+impl<'de, 'a, T: NextExt> de::Deserializer<'de> for &'a mut DottedDeserializer<'a, T> {
+  type Error = Error;
+
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+    let path = take(&mut self.1);
+    self
+      .0
+      .within(&path.clone(), |de| visitor.visit_map(MapAccessFields::new(de, path, None)))
+  }
+
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+    visitor.visit_some(self)
+  }
+
+  fn deserialize_struct<V>(
+    self,
+    _name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value>
+  where
+    V: Visitor<'de>,
+  {
+    let path = take(&mut self.1);
+    self
+      .0
+      .within(&path.clone(), |de| visitor.visit_map(MapAccessFields::new(de, path, Some(fields))))
+  }
+
+  fn deserialize_enum<V>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value>
+  where
+    V: Visitor<'de>,
+  {
+    let path = take(&mut self.1);
+    visitor.visit_enum(DottedEnum(self.0, path))
+  }
+
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+    byte_buf unit unit_struct newtype_struct seq tuple tuple_struct
+    map identifier ignored_any
+  }
+}
+
+/// `path.variant.field = value` for a struct variant, `path.variant = value`
+/// for a variant holding a single value
+struct DottedEnum<'a, T: 'a>(&'a mut Deserializer<T>, String);
+
+impl<'de, 'a, T: NextExt + 'a> EnumAccess<'de> for DottedEnum<'a, T> {
+  type Error = Error;
+  type Variant = DottedVariant<'a, T>;
+
+  fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+  where
+    V: DeserializeSeed<'de>,
+  {
+    let (variant, nested) = match self.0.next_field(&self.1, true)? {
+      Some(field) => field,
+      None => return Err(Error::Custom(format!("expected a `{}.<variant>` key", self.1))),
+    };
+    let name: de::value::StringDeserializer<Error> = variant.into_deserializer();
+    let value = seed.deserialize(name).map_err(|e| self.0.locate(e, true))?;
+    Ok((value, DottedVariant(self.0, nested)))
+  }
+}
+
+/// The variant's own path, or `None` when its value is on the variant's line
+struct DottedVariant<'a, T: 'a>(&'a mut Deserializer<T>, Option<String>);
+
+impl<'de, 'a, T: NextExt + 'a> VariantAccess<'de> for DottedVariant<'a, T> {
+  type Error = Error;
+
+  fn unit_variant(self) -> Result<()> {
+    match self.1 {
+      None => self.0.next_value().map(drop),
+      Some(path) => Err(Error::Custom(format!("`{}` takes no values", path))),
+    }
+  }
+
+  fn newtype_variant_seed<S>(self, seed: S) -> Result<S::Value>
+  where
+    S: DeserializeSeed<'de>,
+  {
+    match self.1 {
+      None => seed
+        .deserialize(&mut ValueDeserializer(&mut *self.0))
+        .map_err(|e| self.0.locate(e, false)),
+      Some(path) => seed.deserialize(&mut DottedDeserializer(self.0, path)),
+    }
+  }
+
+  fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+    match self.1 {
+      None => {
+        de::Deserializer::deserialize_tuple(&mut ValueDeserializer(&mut *self.0), len, visitor)
+          .map_err(|e| self.0.locate(e, false))
+      }
+      Some(path) => Err(Error::Custom(format!("`{}` takes a list, not dotted keys", path))),
+    }
+  }
+
+  fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+  where
+    V: Visitor<'de>,
+  {
+    match self.1 {
+      Some(path) => visitor.visit_map(MapAccessFields::new(self.0, path, Some(fields))),
+      None => Err(
+        self
+          .0
+          .locate(Error::Custom("expected `key.variant.field = value` lines".into()), true),
+      ),
+    }
+  }
+}
+
+/// A run of `[kind:name]` sections, as a map of name to section
+struct GroupDeserializer<'a, T: 'a>(&'a mut Deserializer<T>, String);
+
+impl<'de, 'a, T: NextExt> de::Deserializer<'de> for &'a mut GroupDeserializer<'a, T> {
+  type Error = Error;
+
+  fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+    let kind = take(&mut self.1);
+    let mut group = MapAccessGroup {
+      de: self.0,
+      kind,
+      keys: Keys::default(),
+      aside: Vec::new(),
+    };
+    let value = visitor.visit_map(&mut group);
+    let aside = take(&mut group.aside);
+    group.de.restore(aside);
+    value
+  }
+
+  fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+    visitor.visit_some(self)
+  }
+
+  forward_to_deserialize_any! {
+    bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes
+    byte_buf unit unit_struct newtype_struct seq tuple tuple_struct
+    map struct identifier ignored_any enum
+  }
+}
+
+/// The `[kind:name]` sections of one kind, wherever they are in the file. The
+/// lines passed over to reach those further down are read again once the
+/// group is done. Only a name given twice is an error, in strict mode.
+struct MapAccessGroup<'a, T: NextExt + 'a> {
+  de: &'a mut Deserializer<T>,
+  kind: String,
+  keys: Keys,
+  aside: Vec<Result<Item>>,
+}
+
+impl<'de, 'a, T: NextExt + 'a> MapAccess<'de> for &mut MapAccessGroup<'a, T> {
+  type Error = Error;
+
+  fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+  where
+    K: DeserializeSeed<'de>,
+  {
+    let kind = self.kind.as_str();
+    let mut name = self.de.peek_section()?;
+    if name.as_deref().and_then(group_kind) != Some(kind) {
+      if !self.de.skip_to_group(kind, &mut self.aside) {
+        return Ok(None);
+      }
+      name = self.de.peek_section()?;
+    }
+    let name = name.unwrap_or_default();
+    self.de.next_section()?;
+    let name = name[name.find(':').unwrap_or_default() + 1..]
+      .trim()
+      .to_string();
+    let pos = self.de.position();
+    if let Some(first) = self.keys.seen.get(&name) {
+      if self.de.strictness == Strictness::Strict {
+        return Err(Error::Custom(format!(
+          "duplicate section `[{}:{}]`, the first one is on line {}",
+          self.kind, name, first.line
+        )));
+      }
+    }
+    self.keys.check(self.de.strictness, &name, pos)?;
+    seed
+      .deserialize(name.into_deserializer())
+      .map(Some)
+      .map_err(|e| self.de.locate(e, false))
+  }
+
+  fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+  where
+    V: DeserializeSeed<'de>,
+  {
+    seed.deserialize(&mut SectionDeserializer(&mut *self.de))
+  }
+}
+
+/// `kind` of a `[kind:name]` section header
+fn group_kind(name: &str) -> Option<&str> {
+  name.find(':').map(|i| name[..i].trim())
+}
+
+/// The iterator returned by `Deserializer::sections`, yielding each section's
+/// name with its values
+pub struct Sections<'a, T, V>(&'a mut Deserializer<T>, PhantomData<V>);
+
+impl<'a, T: NextExt, V: DeserializeOwned> Iterator for Sections<'a, T, V> {
+  type Item = Result<(String, V)>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let de = &mut *self.0;
+    let section = match de.peek_kind() {
+      Ok(Some(PeekKind::Section)) => de.next_section(),
+      Ok(Some(PeekKind::Value)) => {
+        let e = de.next_key().and_then(|key| {
+          de.next_value()?;
+          Err(Error::Custom(format!("`{}` is outside a section", key)))
+        });
+        return Some(e.map_err(|e| de.locate(e, true)));
+      }
+      Ok(None) => return None,
+      Err(e) => Err(e),
+    };
+    Some(section.and_then(|name| {
+      let value = V::deserialize(&mut SectionDeserializer(&mut *de))?;
+      Ok((name, value))
+    }))
+  }
+}
+
+/// The elements of a list value, from `a, b` or from a key repeated on consecutive lines
+struct SeqAccessList<'a, T: 'a>(&'a mut Deserializer<T>, std::vec::IntoIter<String>);
+
+impl<'de, 'a, T: NextExt + 'a> SeqAccess<'de> for SeqAccessList<'a, T> {
+  type Error = Error;
+
+  fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>>
+  where
+    S: DeserializeSeed<'de>,
+  {
+    loop {
+      if let Some(value) = self.1.next() {
+        return seed
+          .deserialize(&mut ValueDeserializer(&mut single(value)))
+          .map(Some);
+      }
+      match self.0.next_repeat()? {
+        Some(values) => self.1 = values.into_iter(),
+        None => return Ok(None),
+      }
+    }
+  }
+}
+
+/// A variant written as a value: `name`, or `name(value)` and `name(a, b)` for
+/// the variants holding data. In a list the latter is quoted, `"name(a, b)", c`.
+struct ValueEnum {
+  name: String,
+  args: Option<String>,
+}
+
+impl ValueEnum {
+  fn parse(value: String) -> Result<ValueEnum> {
+    let value = value.trim();
+    let (name, args) = match value.find('(') {
+      Some(i) => match value[i + 1..].strip_suffix(')') {
+        Some(args) => (&value[..i], Some(args.trim().to_string())),
+        None => return Err(Error::Custom(format!("`{}` is missing a closing `)`", value))),
+      },
+      None => (value, None),
+    };
+    Ok(ValueEnum {
+      name: name.trim().to_string(),
+      args,
+    })
+  }
+}
+
+impl<'de> EnumAccess<'de> for ValueEnum {
+  type Error = Error;
+  type Variant = ValueVariant;
+
+  fn variant_seed<V>(self, seed: V) -> Result<(V::Value, ValueVariant)>
+  where
+    V: DeserializeSeed<'de>,
+  {
+    let name: de::value::StringDeserializer<Error> = self.name.into_deserializer();
+    Ok((seed.deserialize(name)?, ValueVariant(self.args)))
+  }
+}
+
+/// The text between the parentheses, if there were any
+struct ValueVariant(Option<String>);
+
+impl<'de> VariantAccess<'de> for ValueVariant {
+  type Error = Error;
+
+  fn unit_variant(self) -> Result<()> {
+    match self.0 {
+      None => Ok(()),
+      Some(_) => Err(Error::Custom("expected a variant without a value".into())),
+    }
+  }
+
+  fn newtype_variant_seed<S>(self, seed: S) -> Result<S::Value>
+  where
+    S: DeserializeSeed<'de>,
+  {
+    match self.0 {
+      Some(value) => seed.deserialize(&mut ValueDeserializer(&mut single(value))),
+      None => Err(Error::Custom("expected `variant(value)`".into())),
+    }
+  }
+
+  fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+    let values = match self.0 {
+      Some(args) => split_list(&args)?,
+      None => return Err(Error::Custom("expected `variant(a, b, ...)`".into())),
+    };
+    let mut de = Deserializer::new(iter::empty::<Result<Item>>());
+    visitor.visit_seq(SeqAccessList(&mut de, values.into_iter()))
+  }
+
+  fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+  where
+    V: Visitor<'de>,
+  {
+    Err(Error::Custom(
+      "a variant with fields is written as `key.variant.field = value` lines".into(),
+    ))
+  }
+}
+
+/// Splits `a, "b, c", d` at the commas outside double quotes. Quoted elements
+/// keep their spaces and may escape `\"` and `\\`, empty elements are skipped.
+fn split_list(value: &str) -> Result<Vec<String>> {
+  let mut elements = Vec::new();
+  let mut chars = value.chars();
+  loop {
+    let mut element = String::new();
+    let mut quoted = false;
+    let mut end = true;
+    while let Some(c) = chars.next() {
+      match c {
+        ',' => {
+          end = false;
+          break;
+        }
+        '"' if element.trim().is_empty() && !quoted => {
+          quoted = true;
+          element.clear();
+          loop {
+            match chars.next() {
+              Some('"') => break,
+              Some('\\') => match chars.next() {
+                Some(c) => element.push(c),
+                None => return Err(Error::Custom("unterminated quote in list".into())),
+              },
+              Some(c) => element.push(c),
+              None => return Err(Error::Custom("unterminated quote in list".into())),
+            }
+          }
+        }
+        c if quoted && c.is_whitespace() => {}
+        c if quoted => {
+          return Err(Error::Custom(format!("unexpected `{}` after quoted list element", c)))
+        }
+        c => element.push(c),
+      }
+    }
+    if quoted {
+      elements.push(element);
+    } else if !element.trim().is_empty() {
+      elements.push(element.trim().into());
+    }
+    if end {
+      return Ok(elements);
+    }
+  }
+}
+
+fn single(value: String) -> Deserializer<iter::Once<Result<Item>>> {
+  Deserializer::new(iter::once(Ok(Item::Value {
+    key: String::new(),
+    value,
+    quoted: false,
+    pos: Position::default(),
+  })))
+}
+
+/// The closest field name within a couple of typos of `key`
+fn suggest(key: &str, fields: &[&'static str]) -> Option<&'static str> {
+  fields
+    .iter()
+    .map(|field| (edit_distance(key, field), *field))
+    .filter(|&(distance, field)| distance <= 2.max(field.len() / 4))
+    .min()
+    .map(|(_, field)| field)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b: Vec<char> = b.chars().collect();
+  let mut row: Vec<usize> = (0..=b.len()).collect();
+  for (i, ca) in a.chars().enumerate() {
+    let mut diagonal = row[0];
+    row[0] = i + 1;
+    for (j, &cb) in b.iter().enumerate() {
+      let above = row[j + 1];
+      row[j + 1] = if ca == cb {
+        diagonal
+      } else {
+        1 + diagonal.min(above).min(row[j])
+      };
+      diagonal = above;
+    }
+  }
+  row[b.len()]
+}
+
 pub fn from_str<T: DeserializeOwned>(s: &str) -> Result<T> {
-  let mut de = Deserializer::new(parse::Parser::from_str(s.as_ref()));
+  let mut de = Deserializer::new(parse::Parser::from_str(s));
   let value = Deserialize::deserialize(&mut de)?;
   de.assert_eof()?;
   Ok(value)
@@ -430,3 +1434,15 @@ pub fn from_read<R: io::Read, T: DeserializeOwned>(reader: R) -> Result<T> {
   de.assert_eof()?;
   Ok(value)
 }
+
+pub fn from_file<P: AsRef<Path>, T: DeserializeOwned>(path: P) -> Result<T> {
+  let mut de = Deserializer::from_file(path).map_err(|e| Error::Custom(e.to_string()))?;
+  let value = Deserialize::deserialize(&mut de)?;
+  de.assert_eof()?;
+  Ok(value)
+}
+
+/// Deserializes a single value the way it would be read from `key = value`
+pub fn from_value<T: DeserializeOwned>(value: &str) -> Result<T> {
+  Deserialize::deserialize(&mut ValueDeserializer(&mut single(value.into())))
+}
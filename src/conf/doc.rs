@@ -0,0 +1,222 @@
+use super::de::Error;
+use super::parse::{self, Item, Parser};
+use super::ser::quote;
+use super::void::Void;
+use std::fmt::{self, Formatter};
+use std::io::{self, Read};
+use std::ops::Range;
+use std::str::FromStr;
+
+/// A config file kept line by line, so keys can be changed and the file written
+/// back with its comments, blank lines and order untouched. `${VAR}` references
+/// are left as written and `include` lines are not followed.
+#[derive(Debug, Default)]
+pub struct Document {
+  lines: Vec<Line>,
+}
+
+#[derive(Debug)]
+struct Line {
+  /// As written, continued lines joined with `\n`
+  raw: String,
+  item: Item,
+}
+
+impl Document {
+  pub fn from_read<R: Read>(mut reader: R) -> Result<Document, Error> {
+    let mut text = String::new();
+    reader
+      .read_to_string(&mut text)
+      .map_err(|e: io::Error| Error::Custom(e.to_string()))?;
+    text.parse()
+  }
+
+  /// The value of `key` in `section`, `None` for the keys above the first section.
+  /// A key repeated to build a list gives its first line.
+  pub fn get(&self, section: Option<&str>, key: &str) -> Option<&str> {
+    let range = self.section(section)?;
+    self.lines[range].iter().find_map(|line| match line.item {
+      Item::Value {
+        key: ref k,
+        ref value,
+        ..
+      } if k == key => Some(&**value),
+      _ => None,
+    })
+  }
+
+  /// Every `key = value` line with the section it is in, in file order
+  pub fn entries(&self) -> impl Iterator<Item = (Option<&str>, &str, &str)> {
+    let mut section = None;
+    self.lines.iter().filter_map(move |line| match line.item {
+      Item::Section { ref name, .. } => {
+        section = Some(&**name);
+        None
+      }
+      Item::Value {
+        ref key, ref value, ..
+      } => Some((section, &**key, &**value)),
+      _ => None,
+    })
+  }
+
+  /// The `[name]` sections, in file order
+  pub fn sections(&self) -> impl Iterator<Item = &str> {
+    self.lines.iter().filter_map(|line| match line.item {
+      Item::Section { ref name, .. } => Some(&**name),
+      _ => None,
+    })
+  }
+
+  /// Sets `key` in `section`, quoting `value` if it needs to be. An existing line
+  /// is rewritten in place and keeps its inline comment, further lines of a
+  /// repeated key are dropped. A new key goes after the last one in the
+  /// section, a new section at the end of the file. Section names are matched
+  /// and written trimmed, the way the parser reads them.
+  pub fn set(&mut self, section: Option<&str>, key: &str, value: &str) {
+    let range = match self.section(section) {
+      Some(range) => range,
+      None => {
+        let name = section.unwrap_or_default().trim();
+        if !self.lines.is_empty() {
+          self.push(String::new());
+        }
+        self.lines.push(Line {
+          raw: format!("[{}]", name),
+          item: Item::Section {
+            name: name.into(),
+            pos: Default::default(),
+          },
+        });
+        self.lines.len()..self.lines.len()
+      }
+    };
+    let found: Vec<_> = self.find(range.clone(), key).collect();
+    let item = Item::Value {
+      key: key.into(),
+      value: value.into(),
+      quoted: false,
+      pos: Default::default(),
+    };
+    match found.split_first() {
+      Some((&i, repeats)) => {
+        let line = &mut self.lines[i];
+        let (indent, comment) = surroundings(&line.raw);
+        line.raw = format!("{}{} = {}{}", indent, key, quote(value), comment);
+        line.item = item;
+        for &i in repeats.iter().rev() {
+          self.lines.remove(i);
+        }
+      }
+      None => {
+        let at = self.lines[range.clone()]
+          .iter()
+          .rposition(|line| matches!(line.item, Item::Value { .. }))
+          .map(|i| range.start + i + 1)
+          .unwrap_or_else(|| match section {
+            Some(_) => range.start,
+            None => {
+              let blank = self.lines[range.clone()]
+                .iter()
+                .rev()
+                .take_while(|line| matches!(line.item, Item::Empty))
+                .count();
+              range.end - blank
+            }
+          });
+        let raw = format!("{} = {}", key, quote(value));
+        self.lines.insert(at, Line { raw, item });
+      }
+    }
+  }
+
+  /// Removes every line of `key` in `section`, true if there was one
+  pub fn remove(&mut self, section: Option<&str>, key: &str) -> bool {
+    let found: Vec<_> = match self.section(section) {
+      Some(range) => self.find(range, key).collect(),
+      None => return false,
+    };
+    for &i in found.iter().rev() {
+      self.lines.remove(i);
+    }
+    !found.is_empty()
+  }
+
+  /// The lines after the `[name]` line up to the next section, or the lines
+  /// before the first section
+  fn section(&self, name: Option<&str>) -> Option<Range<usize>> {
+    let is_section = |line: &Line| matches!(line.item, Item::Section { .. });
+    let start = match name {
+      None => 0,
+      Some(name) => {
+        let i = self.lines.iter().position(|line| match line.item {
+          Item::Section { name: ref n, .. } => n == name.trim(),
+          _ => false,
+        })?;
+        i + 1
+      }
+    };
+    let end = self.lines[start..]
+      .iter()
+      .position(is_section)
+      .map_or(self.lines.len(), |i| start + i);
+    Some(start..end)
+  }
+
+  fn find<'a>(&'a self, range: Range<usize>, key: &'a str) -> impl Iterator<Item = usize> + 'a {
+    range.filter(move |&i| matches!(self.lines[i].item, Item::Value { key: ref k, .. } if k == key))
+  }
+
+  fn push(&mut self, raw: String) {
+    let item = Parser::<()>::parse_next::<Void, _>(Some(&raw), 0, false)
+      .ok()
+      .flatten()
+      .unwrap_or(Item::Empty);
+    self.lines.push(Line { raw, item });
+  }
+}
+
+/// The indentation before the key and the inline comment after the value
+fn surroundings(raw: &str) -> (&str, &str) {
+  let indent = &raw[..raw.len() - raw.trim_start().len()];
+  let value = raw.split_once('=').map_or("", |(_, value)| value);
+  let kept = parse::strip_comment(value).trim_end();
+  (indent, value[kept.len()..].trim_end())
+}
+
+impl FromStr for Document {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Document, Error> {
+    let mut lines = Vec::new();
+    let mut input = s.lines().enumerate();
+    while let Some((i, first)) = input.next() {
+      let mut raw = first.to_string();
+      let mut logical = first.to_string();
+      while parse::continues(&logical) {
+        logical.truncate(logical.trim_end().len() - 1);
+        match input.next() {
+          Some((_, next)) => {
+            raw.push('\n');
+            raw.push_str(next);
+            logical.push_str(next.trim_start());
+          }
+          None => break,
+        }
+      }
+      let item =
+        Parser::<()>::parse_next::<Void, _>(Some(&logical), i + 1, false)?.unwrap_or(Item::Empty);
+      lines.push(Line { raw, item });
+    }
+    Ok(Document { lines })
+  }
+}
+
+impl fmt::Display for Document {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    for line in &self.lines {
+      writeln!(f, "{}", line.raw)?;
+    }
+    Ok(())
+  }
+}
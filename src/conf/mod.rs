@@ -1,7 +1,14 @@
 mod de;
-mod error;
+mod doc;
 mod parse;
 mod result;
+mod ser;
 mod void;
 
-pub use de::{from_buf_read, from_read, from_str, Deserializer, Error};
+pub use de::{
+  from_buf_read, from_file, from_read, from_str, from_value, Deserializer, Error, NextExt,
+  Sections, Strictness,
+};
+pub use doc::Document;
+pub use parse::Position;
+pub use ser::{to_string, to_writer, Serializer};
@@ -1,16 +1,19 @@
 use super::result::*;
 use super::void::Void;
 use std::fmt::Formatter;
+use std::fs::{self, File};
 use std::option::Option::{None, Some};
-use std::{error, fmt, io, str};
+use std::path::{Path, PathBuf};
+use std::{env, error, fmt, io, str};
 
 pub struct Parser<T> {
   input: T,
+  line: usize,
 }
 
 impl<T> Parser<T> {
   fn new(input: T) -> Self {
-    Parser { input }
+    Parser { input, line: 0 }
   }
 }
 pub struct OkIter<I>(I);
@@ -42,26 +45,65 @@ impl<R: io::Read> Parser<io::Lines<io::BufReader<R>>> {
 }
 
 impl<T> Parser<T> {
-  fn parse_next<E, S: AsRef<str>>(line: Option<S>) -> Result<Option<Item>, Error<E>> {
+  /// Parses one line, `interpolate` expands `${VAR}` references in values.
+  pub(super) fn parse_next<E, S: AsRef<str>>(
+    line: Option<S>,
+    number: usize,
+    interpolate: bool,
+  ) -> Result<Option<Item>, Error<E>> {
     let line = match line {
       Some(line) => line,
       None => return Ok(None),
     };
     let line = line.as_ref();
-    if line.starts_with(';') || line.starts_with('#') {
+    let trimmed = line.trim();
+    if trimmed.starts_with(';') || trimmed.starts_with('#') {
       Ok(Some(Item::Comment { text: line.into() }))
+    } else if trimmed.starts_with('[') {
+      let start = line.len() - trimmed.len();
+      if trimmed.ends_with(']') && trimmed.len() > 1 {
+        let name = trimmed[1..trimmed.len() - 1].trim();
+        Ok(Some(Item::Section {
+          name: name.into(),
+          pos: Position::new(line, number, start, trimmed),
+        }))
+      } else {
+        let pos = Position::new(line, number, start, trimmed);
+        Err(Error::Syntax(SyntaxError::MissingBracket, pos))
+      }
     } else {
-      let mut line = line.splitn(2, '=');
-      if let Some(key) = line.next() {
-        if let Some(value) = line.next() {
+      let mut parts = line.splitn(2, '=');
+      if let Some(key) = parts.next() {
+        if let Some(value) = parts.next() {
+          let value = strip_comment(value);
+          let start = key.len() + 1 + value.len() - value.trim_start().len();
+          let value = value.trim();
+          let pos = Position::new(line, number, start, value);
+          let (text, quoted) = match closing_quote(value) {
+            Some(end) if end == value.len() - 1 => (&value[1..end], true),
+            // Only a list goes on after a quoted value, `"a, b", c`
+            Some(end) if !value[end + 1..].trim_start().starts_with(',') => {
+              let rest = value[end + 1..].trim_start();
+              let pos = Position::new(line, number, start + value.len() - rest.len(), rest);
+              return Err(Error::Syntax(SyntaxError::TextAfterQuote, pos));
+            }
+            None if value.starts_with('"') => {
+              return Err(Error::Syntax(SyntaxError::UnterminatedQuote, pos))
+            }
+            _ => (value, false),
+          };
           Ok(Some(Item::Value {
             key: key.trim().into(),
-            value: value.trim().into(),
+            value: Self::expand(text, quoted, interpolate, &pos)?,
+            quoted,
+            pos,
           }))
-        } else if key.is_empty() {
+        } else if key.trim().is_empty() {
           Ok(Some(Item::Empty))
         } else {
-          Err(Error::Syntax(SyntaxError::MissingEquals))
+          let start = line.len() - line.trim_start().len();
+          let pos = Position::new(line, number, start, line.trim());
+          Err(Error::Syntax(SyntaxError::MissingEquals, pos))
         }
       } else {
         unreachable!()
@@ -70,35 +112,361 @@ impl<T> Parser<T> {
   }
 }
 
+impl<T> Parser<T> {
+  /// Expands `${VAR}` and `${VAR:-default}` from the environment, the default is
+  /// used when `VAR` is unset or empty. Between quotes `\"`, `\\`, `\$`, `\n` and
+  /// `\t` are escapes as well.
+  fn expand<E>(
+    text: &str,
+    quoted: bool,
+    interpolate: bool,
+    pos: &Position,
+  ) -> Result<String, Error<E>> {
+    let offset = if quoted { 1 } else { 0 };
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+      let rest = &text[i..];
+      if c == '\\' && quoted {
+        let escaped = rest[1..].chars().next().unwrap_or('\\');
+        out.push(match escaped {
+          'n' => '\n',
+          't' => '\t',
+          c => c,
+        });
+        i += 1 + escaped.len_utf8().min(rest.len() - 1);
+      } else if interpolate && rest.starts_with("${") {
+        let at = |e| {
+          let len = rest.find('}').map_or(rest.len(), |end| end + 1);
+          Error::Syntax(e, pos.at(offset + i, &rest[..len]))
+        };
+        let end = rest
+          .find('}')
+          .ok_or_else(|| at(SyntaxError::UnterminatedVariable))?;
+        let var = &rest[2..end];
+        let (name, default) = match var.find(":-") {
+          Some(i) => (&var[..i], Some(&var[i + 2..])),
+          None => (var, None),
+        };
+        let found = env::var(name)
+          .ok()
+          .filter(|v| default.is_none() || !v.is_empty());
+        match (found, default) {
+          (Some(found), _) => out.push_str(&found),
+          (None, Some(default)) => out.push_str(default),
+          (None, None) => return Err(at(SyntaxError::UnsetVariable)),
+        }
+        i += end + 1;
+      } else {
+        out.push(c);
+        i += c.len_utf8();
+      }
+    }
+    Ok(out)
+  }
+}
+
+/// The value up to an inline comment, a `#` or `;` after whitespace outside quotes
+pub(super) fn strip_comment(value: &str) -> &str {
+  let mut quoted = false;
+  let mut escaped = false;
+  let mut prev = '=';
+  for (i, c) in value.char_indices() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' if quoted => escaped = true,
+      '"' => quoted = !quoted,
+      '#' | ';' if !quoted && prev.is_whitespace() => return &value[..i],
+      _ => {}
+    }
+    prev = c;
+  }
+  value
+}
+
+/// Where the quote opening `value` is closed, if it starts with one
+fn closing_quote(value: &str) -> Option<usize> {
+  let mut chars = value.char_indices();
+  if let Some((_, '"')) = chars.next() {
+    while let Some((i, c)) = chars.next() {
+      match c {
+        '\\' => {
+          chars.next();
+        }
+        '"' => return Some(i),
+        _ => {}
+      }
+    }
+  }
+  None
+}
+
+/// A line ending in an odd number of backslashes carries on to the next one,
+/// a value that ends in a backslash has to be quoted
+pub(super) fn continues(line: &str) -> bool {
+  let line = line.trim();
+  !line.starts_with(';')
+    && !line.starts_with('#')
+    && (line.len() - line.trim_end_matches('\\').len()) % 2 == 1
+}
+
 impl<E, S: AsRef<str>, T: Iterator<Item = Result<S, E>>> Iterator for Parser<T> {
   type Item = Result<Item, Error<E>>;
 
   fn next(&mut self) -> Option<Self::Item> {
-    self
-      .input
-      .next_invert()
-      .map_err(Error::Inner)
-      .and_then(|l| Self::parse_next(l))
-      .invert()
+    let mut line = match self.input.next_invert() {
+      Ok(Some(line)) => line.as_ref().to_string(),
+      Ok(None) => return None,
+      Err(e) => return Some(Err(Error::Inner(e))),
+    };
+    self.line += 1;
+    let number = self.line;
+    while continues(&line) {
+      let end = line.trim_end().len() - 1;
+      match self.input.next_invert() {
+        Ok(Some(next)) => {
+          line.truncate(end);
+          line.push_str(next.as_ref().trim_start());
+        }
+        Ok(None) => {
+          let pos = Position::new(&line, self.line, end, "\\");
+          return Some(Err(Error::Syntax(SyntaxError::TrailingBackslash, pos)));
+        }
+        Err(e) => return Some(Err(Error::Inner(e))),
+      }
+      self.line += 1;
+    }
+    Self::parse_next(Some(line), number, true).invert()
+  }
+}
+
+/// Parses a file, reading the files named by `include = <path or glob>` lines in
+/// their place. Relative paths start from the including file's directory, glob
+/// matches are read in sorted order and a file may not include itself again.
+pub struct FileParser {
+  stack: Vec<Source>,
+}
+
+enum Source {
+  Pending(PathBuf, Position),
+  Open(PathBuf, Parser<io::Lines<io::BufReader<File>>>),
+}
+
+impl FileParser {
+  pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+    let path = path.as_ref();
+    let parser = Parser::from_read(File::open(path)?);
+    Ok(FileParser {
+      stack: vec![Source::Open(fs::canonicalize(path)?, parser)],
+    })
+  }
+
+  fn open_include(&self, path: PathBuf, pos: Position) -> Result<Source, Error<io::Error>> {
+    let in_path = |e: io::Error| {
+      let e = io::Error::new(e.kind(), format!("{}: {}", path.display(), e));
+      Error::Include(e, pos.clone())
+    };
+    let canonical = fs::canonicalize(&path).map_err(in_path)?;
+    let open = |source: &Source| matches!(source, Source::Open(p, _) if *p == canonical);
+    if self.stack.iter().any(open) {
+      return Err(Error::Syntax(SyntaxError::IncludeCycle, pos));
+    }
+    let parser = Parser::from_read(File::open(&path).map_err(in_path)?);
+    Ok(Source::Open(canonical, parser))
+  }
+
+  /// The files an `include` line names, in reverse so they pop off the stack in order
+  fn expand(from: &Path, pattern: &str, pos: &Position) -> Result<Vec<Source>, Error<io::Error>> {
+    let pattern = from.parent().unwrap_or_else(|| Path::new("")).join(pattern);
+    let pattern = pattern.to_string_lossy();
+    let mut paths = if pattern.contains(|c| "*?[".contains(c)) {
+      glob::glob(&pattern)
+        .map_err(|_| Error::Syntax(SyntaxError::BadPattern, pos.clone()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::Include(e.into(), pos.clone()))?
+    } else {
+      vec![PathBuf::from(&*pattern)]
+    };
+    paths.sort();
+    Ok(
+      paths
+        .into_iter()
+        .rev()
+        .map(|path| Source::Pending(path, pos.clone()))
+        .collect(),
+    )
+  }
+}
+
+impl Iterator for FileParser {
+  type Item = Result<Item, Error<io::Error>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let (path, mut parser) = match self.stack.pop()? {
+        Source::Open(path, parser) => (path, parser),
+        Source::Pending(path, pos) => match self.open_include(path, pos) {
+          Ok(source) => {
+            self.stack.push(source);
+            continue;
+          }
+          Err(e) => return Some(Err(e)),
+        },
+      };
+      let file = path.display().to_string();
+      let next = parser.next().map(|item| match item {
+        Ok(mut item) => {
+          if let Some(pos) = item.pos_mut() {
+            pos.file = Some(file);
+          }
+          Ok(item)
+        }
+        Err(Error::Syntax(e, pos)) => Err(Error::Syntax(
+          e,
+          Position {
+            file: Some(file),
+            ..pos
+          },
+        )),
+        Err(e) => Err(e),
+      });
+      match next {
+        Some(Ok(Item::Value {
+          ref key,
+          ref value,
+          ref pos,
+          ..
+        }))
+          if key == "include" =>
+        {
+          let includes = Self::expand(&path, value, pos);
+          self.stack.push(Source::Open(path, parser));
+          match includes {
+            Ok(includes) => self.stack.extend(includes),
+            Err(e) => return Some(Err(e)),
+          }
+        }
+        Some(next) => {
+          self.stack.push(Source::Open(path, parser));
+          return Some(next);
+        }
+        None => {}
+      }
+    }
   }
 }
 
 #[derive(Debug)]
 pub enum Item {
   Empty,
-  Value { key: String, value: String },
-  Comment { text: String },
+  Value {
+    key: String,
+    value: String,
+    /// The value was written between double quotes
+    quoted: bool,
+    pos: Position,
+  },
+  /// `[name]`, the values below it up to the next section belong to it
+  Section {
+    name: String,
+    pos: Position,
+  },
+  Comment {
+    text: String,
+  },
+}
+
+impl Item {
+  pub fn pos_mut(&mut self) -> Option<&mut Position> {
+    match self {
+      Item::Value { pos, .. } | Item::Section { pos, .. } => Some(pos),
+      Item::Empty | Item::Comment { .. } => None,
+    }
+  }
+}
+
+/// Where an item sits in the source, `column` and `len` span the value
+#[derive(Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Position {
+  pub file: Option<String>,
+  pub line: usize,
+  pub column: usize,
+  pub len: usize,
+  pub text: String,
+}
+
+impl Position {
+  fn new(text: &str, line: usize, start: usize, span: &str) -> Self {
+    Position {
+      file: None,
+      line,
+      column: text[..start].chars().count() + 1,
+      len: span.chars().count().max(1),
+      text: text.into(),
+    }
+  }
+
+  /// The same line, spanning `span` found `offset` bytes into the value
+  fn at(&self, offset: usize, span: &str) -> Self {
+    let start = self
+      .text
+      .char_indices()
+      .nth(self.column - 1)
+      .map_or(0, |(i, _)| i);
+    Position {
+      file: self.file.clone(),
+      ..Position::new(&self.text, self.line, start + offset, span)
+    }
+  }
+
+  /// The same line, spanning the key instead of the value
+  pub fn key_span(&self) -> Self {
+    let key = self.text.split('=').next().unwrap_or_default();
+    let start = key.len() - key.trim_start().len();
+    Position {
+      file: self.file.clone(),
+      ..Position::new(&self.text, self.line, start, key.trim())
+    }
+  }
+}
+
+impl fmt::Display for Position {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let number = self.line.to_string();
+    let pad = " ".repeat(number.len());
+    writeln!(f, "{} |", pad)?;
+    writeln!(f, "{} | {}", number, self.text)?;
+    write!(f, "{} | {}{}", pad, " ".repeat(self.column - 1), "^".repeat(self.len))
+  }
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum SyntaxError {
   MissingEquals,
+  MissingBracket,
+  IncludeCycle,
+  BadPattern,
+  UnterminatedQuote,
+  TextAfterQuote,
+  TrailingBackslash,
+  UnterminatedVariable,
+  UnsetVariable,
 }
 
 impl fmt::Display for SyntaxError {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     match *self {
       SyntaxError::MissingEquals => write!(f, "missing '='"),
+      SyntaxError::MissingBracket => write!(f, "missing ']'"),
+      SyntaxError::IncludeCycle => write!(f, "file includes itself"),
+      SyntaxError::BadPattern => write!(f, "invalid include pattern"),
+      SyntaxError::UnterminatedQuote => write!(f, "missing closing '\"'"),
+      SyntaxError::TextAfterQuote => write!(f, "unexpected text after closing '\"'"),
+      SyntaxError::TrailingBackslash => {
+        write!(f, "'\\' continues past the end of the file, quote a value ending in '\\'")
+      }
+      SyntaxError::UnterminatedVariable => write!(f, "missing '}}' after '${{'"),
+      SyntaxError::UnsetVariable => write!(f, "environment variable is not set"),
     }
   }
 }
@@ -106,7 +474,9 @@ impl fmt::Display for SyntaxError {
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Error<E> {
   Inner(E),
-  Syntax(SyntaxError),
+  Syntax(SyntaxError, Position),
+  /// Reading a file named by the `include` line at the position failed
+  Include(E, Position),
 }
 
 impl<E> From<E> for Error<E> {
@@ -118,8 +488,8 @@ impl<E> From<E> for Error<E> {
 impl<E: fmt::Display> fmt::Display for Error<E> {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     match *self {
-      Error::Inner(ref e) => fmt::Display::fmt(e, f),
-      Error::Syntax(e) => write!(f, "syntax error {}", e),
+      Error::Inner(ref e) | Error::Include(ref e, _) => fmt::Display::fmt(e, f),
+      Error::Syntax(e, _) => write!(f, "syntax error {}", e),
     }
   }
 }
@@ -0,0 +1,528 @@
+use super::de::Error;
+use serde::ser::{self, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::{io, result};
+
+type Result<T> = result::Result<T, Error>;
+
+impl ser::Error for Error {
+  fn custom<T>(msg: T) -> Self
+  where
+    T: Display,
+  {
+    Error::Custom(msg.to_string())
+  }
+}
+
+/// A serialized value before it is laid out as lines
+#[derive(Debug)]
+enum Node {
+  /// `None` and unit, the key is left out
+  Absent,
+  Value(String),
+  List(Vec<String>),
+  Table(Vec<(String, Node)>),
+  /// A variant holding a struct, written below `key.variant`
+  Variant(String, Box<Node>),
+}
+
+/// Writes a struct as `key = value` lines followed by its sections. A struct
+/// field becomes a `[name]` section and a map of structs a run of `[name:key]`
+/// sections, the way `Deserializer` reads them. Anything nested deeper, and
+/// variants holding a struct, are written as dotted `key.field = value` lines.
+pub struct Serializer<'a, W> {
+  writer: W,
+  docs: &'a [(&'a str, &'a str)],
+  defaults: Option<HashMap<String, String>>,
+}
+
+impl<'a, W: io::Write> Serializer<'a, W> {
+  pub fn new(writer: W) -> Self {
+    Serializer {
+      writer,
+      docs: &[],
+      defaults: None,
+    }
+  }
+
+  /// Writes the doc of each top level key as `#` lines above it
+  pub fn docs(mut self, docs: &'a [(&'a str, &'a str)]) -> Self {
+    self.docs = docs;
+    self
+  }
+
+  /// Notes the value each top level key has in `defaults` above it
+  pub fn defaults<T: Serialize + ?Sized>(mut self, defaults: &T) -> Result<Self> {
+    let mut values = HashMap::new();
+    for (key, node) in table(defaults)? {
+      if let Some(text) = text(&node).ok().flatten() {
+        values.insert(key, text);
+      }
+    }
+    self.defaults = Some(values);
+    Ok(self)
+  }
+
+  pub fn serialize<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    let annotated = !self.docs.is_empty() || self.defaults.is_some();
+    let mut sections = Vec::new();
+    for (key, node) in table(value)? {
+      if let Node::Table(_) = node {
+        sections.push((key, node));
+        continue;
+      }
+      if annotated {
+        self.annotate(&key)?;
+      }
+      let lines = lines_of(&key, node)?;
+      if lines.is_empty() && annotated {
+        self.write(format_args!("#{} =\n", key))?;
+      }
+      for line in lines {
+        self.write(line)?;
+      }
+      if annotated {
+        self.write("\n")?;
+      }
+    }
+    for (key, node) in sections {
+      if annotated {
+        self.annotate(&key)?;
+      }
+      for (name, lines) in sections_of(&key, node)? {
+        self.write(format_args!("\n[{}]\n", name))?;
+        for line in lines {
+          self.write(line)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  pub fn into_inner(self) -> W {
+    self.writer
+  }
+
+  fn annotate(&mut self, key: &str) -> Result<()> {
+    let doc = self
+      .docs
+      .iter()
+      .find(|(k, _)| *k == key)
+      .map(|&(_, doc)| doc);
+    for line in doc.into_iter().flat_map(str::lines) {
+      self.write(format_args!("# {}\n", line))?;
+    }
+    let default = self.defaults.as_ref().and_then(|d| d.get(key)).cloned();
+    if let Some(default) = default {
+      self.write(format_args!("# default: {}\n", default))?;
+    }
+    Ok(())
+  }
+
+  fn write<T: Display>(&mut self, text: T) -> Result<()> {
+    write!(self.writer, "{}", text).map_err(|e| Error::Custom(e.to_string()))
+  }
+}
+
+/// The fields of the top level struct or map
+fn table<T: Serialize + ?Sized>(value: &T) -> Result<Vec<(String, Node)>> {
+  match value.serialize(NodeSerializer)? {
+    Node::Table(fields) => Ok(fields),
+    Node::Absent => Ok(Vec::new()),
+    _ => Err(Error::Custom("only a struct or a map can be written as a config".into())),
+  }
+}
+
+/// `key = value\n`, a `key.field = value\n` line per value of a table or
+/// variant, or nothing for an absent value
+fn lines_of(key: &str, node: Node) -> Result<Vec<String>> {
+  match node {
+    Node::Table(entries) => {
+      let mut lines = Vec::new();
+      for (field, node) in entries {
+        lines.extend(lines_of(&format!("{}.{}", key, field), node)?);
+      }
+      Ok(lines)
+    }
+    Node::Variant(variant, node) => lines_of(&format!("{}.{}", key, variant), *node),
+    node => Ok(
+      text(&node)?
+        .into_iter()
+        .map(|text| match &*text {
+          "" => format!("{} =\n", key),
+          text => format!("{} = {}\n", key, text),
+        })
+        .collect(),
+    ),
+  }
+}
+
+/// What goes after `key =`, `None` for what doesn't fit on one line
+fn text(node: &Node) -> Result<Option<String>> {
+  match node {
+    Node::Absent | Node::Table(_) | Node::Variant(..) => Ok(None),
+    Node::Value(value) => Ok(Some(quote(value))),
+    Node::List(list) => join(list).map(Some),
+  }
+}
+
+/// `[key]` for a table of values, `[key:name]` for each entry of a table of tables
+fn sections_of(key: &str, node: Node) -> Result<Vec<(String, Vec<String>)>> {
+  let entries = match node {
+    Node::Table(entries) => entries,
+    _ => return Ok(Vec::new()),
+  };
+  let grouped = !entries.is_empty()
+    && entries
+      .iter()
+      .all(|(_, node)| matches!(node, Node::Table(_)));
+  let mut sections = Vec::new();
+  if grouped {
+    for (name, node) in entries {
+      sections.push((format!("{}:{}", key, name), lines(node)?));
+    }
+    sections.sort_by(|a, b| a.0.cmp(&b.0));
+  } else if !entries.is_empty() {
+    sections.push((key.into(), lines(Node::Table(entries))?));
+  }
+  Ok(sections)
+}
+
+fn lines(node: Node) -> Result<Vec<String>> {
+  let mut lines = Vec::new();
+  if let Node::Table(entries) = node {
+    for (key, node) in entries {
+      lines.extend(lines_of(&key, node)?);
+    }
+  }
+  Ok(lines)
+}
+
+/// A value as it is written after `=`, quoted when it would not read back as is
+pub(super) fn quote(value: &str) -> String {
+  let plain = !value.is_empty()
+    && value.trim() == value
+    && !value.starts_with('"')
+    && !value.ends_with('\\')
+    && !value.contains(['#', ';', '$', '\n', '\t']);
+  if plain {
+    value.into()
+  } else {
+    escape(value)
+  }
+}
+
+/// `value` in double quotes, with the escapes the parser understands
+fn escape(value: &str) -> String {
+  let mut quoted = String::from("\"");
+  for c in value.chars() {
+    match c {
+      '"' | '\\' | '$' => {
+        quoted.push('\\');
+        quoted.push(c);
+      }
+      '\n' => quoted.push_str("\\n"),
+      '\t' => quoted.push_str("\\t"),
+      c => quoted.push(c),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+/// `a, b, "c, d"`. A single element is quoted as a whole value so it stays one element.
+fn join(list: &[String]) -> Result<String> {
+  match list {
+    [value] if value.contains(',') => return Ok(escape(value)),
+    [value] => return Ok(quote(value)),
+    _ => {}
+  }
+  let mut elements = Vec::new();
+  for value in list {
+    if value.contains("${") {
+      return Err(Error::Custom(format!("list element `{}` can't be written", value)));
+    }
+    let plain = !value.is_empty()
+      && value.trim() == value
+      && !value.contains([',', '"', '\\', '#', ';', '\n']);
+    if plain {
+      elements.push(value.clone());
+    } else {
+      let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+      elements.push(format!("\"{}\"", escaped));
+    }
+  }
+  Ok(elements.join(", "))
+}
+
+pub fn to_writer<W: io::Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<()> {
+  Serializer::new(writer).serialize(value)
+}
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String> {
+  let mut buf = Vec::new();
+  to_writer(&mut buf, value)?;
+  String::from_utf8(buf).map_err(|e| Error::Custom(e.to_string()))
+}
+
+struct NodeSerializer;
+
+fn unsupported<T>(what: &str) -> Result<T> {
+  Err(Error::Custom(format!("{} can't be written to a config", what)))
+}
+
+macro_rules! serialize_display {
+  ($($method:ident: $ty:ty)*) => {$(
+    fn $method(self, v: $ty) -> Result<Node> {
+      Ok(Node::Value(v.to_string()))
+    }
+  )*};
+}
+
+impl ser::Serializer for NodeSerializer {
+  type Ok = Node;
+  type Error = Error;
+  type SerializeSeq = SeqSerializer;
+  type SerializeTuple = SeqSerializer;
+  type SerializeTupleStruct = SeqSerializer;
+  type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+  type SerializeMap = TableSerializer;
+  type SerializeStruct = TableSerializer;
+  type SerializeStructVariant = VariantSerializer<TableSerializer>;
+
+  serialize_display! {
+    serialize_bool: bool serialize_i8: i8 serialize_i16: i16 serialize_i32: i32
+    serialize_i64: i64 serialize_u8: u8 serialize_u16: u16 serialize_u32: u32
+    serialize_u64: u64 serialize_f32: f32 serialize_f64: f64 serialize_char: char
+    serialize_str: &str
+  }
+
+  fn serialize_bytes(self, _v: &[u8]) -> Result<Node> {
+    unsupported("bytes")
+  }
+
+  fn serialize_none(self) -> Result<Node> {
+    Ok(Node::Absent)
+  }
+
+  fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Node> {
+    value.serialize(self)
+  }
+
+  fn serialize_unit(self) -> Result<Node> {
+    Ok(Node::Absent)
+  }
+
+  fn serialize_unit_struct(self, _name: &'static str) -> Result<Node> {
+    Ok(Node::Absent)
+  }
+
+  fn serialize_unit_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+  ) -> Result<Node> {
+    Ok(Node::Value(variant.into()))
+  }
+
+  fn serialize_newtype_struct<T: Serialize + ?Sized>(
+    self,
+    _name: &'static str,
+    value: &T,
+  ) -> Result<Node> {
+    value.serialize(self)
+  }
+
+  fn serialize_newtype_variant<T: Serialize + ?Sized>(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+    value: &T,
+  ) -> Result<Node> {
+    match value.serialize(NodeSerializer)? {
+      node @ Node::Table(_) | node @ Node::Variant(..) => {
+        Ok(Node::Variant(variant.into(), Box::new(node)))
+      }
+      node => Ok(Node::Value(format!("{}({})", variant, inline(&node)?))),
+    }
+  }
+
+  fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer> {
+    Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or(0))))
+  }
+
+  fn serialize_tuple(self, len: usize) -> Result<SeqSerializer> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_tuple_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+    len: usize,
+  ) -> Result<Self::SerializeTupleVariant> {
+    Ok(VariantSerializer(variant, SeqSerializer(Vec::with_capacity(len))))
+  }
+
+  fn serialize_map(self, _len: Option<usize>) -> Result<TableSerializer> {
+    Ok(TableSerializer::default())
+  }
+
+  fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<TableSerializer> {
+    Ok(TableSerializer::default())
+  }
+
+  fn serialize_struct_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+    _len: usize,
+  ) -> Result<Self::SerializeStructVariant> {
+    Ok(VariantSerializer(variant, TableSerializer::default()))
+  }
+}
+
+struct SeqSerializer(Vec<String>);
+
+impl SeqSerializer {
+  fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    match value.serialize(NodeSerializer)? {
+      Node::Value(value) => self.0.push(value),
+      Node::Absent => {}
+      _ => return unsupported("a nested list"),
+    }
+    Ok(())
+  }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+  type Ok = Node;
+  type Error = Error;
+
+  fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<Node> {
+    Ok(Node::List(self.0))
+  }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+  type Ok = Node;
+  type Error = Error;
+
+  fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<Node> {
+    Ok(Node::List(self.0))
+  }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+  type Ok = Node;
+  type Error = Error;
+
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<Node> {
+    Ok(Node::List(self.0))
+  }
+}
+
+#[derive(Default)]
+struct TableSerializer {
+  entries: Vec<(String, Node)>,
+  key: Option<String>,
+}
+
+impl ser::SerializeMap for TableSerializer {
+  type Ok = Node;
+  type Error = Error;
+
+  fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+    match key.serialize(NodeSerializer)? {
+      Node::Value(key) => self.key = Some(key),
+      _ => return unsupported("a map key that isn't a single value"),
+    }
+    Ok(())
+  }
+
+  fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    let key = self.key.take().ok_or(Error::InvalidState)?;
+    self.entries.push((key, value.serialize(NodeSerializer)?));
+    Ok(())
+  }
+
+  fn end(self) -> Result<Node> {
+    Ok(Node::Table(self.entries))
+  }
+}
+
+impl ser::SerializeStruct for TableSerializer {
+  type Ok = Node;
+  type Error = Error;
+
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+    self
+      .entries
+      .push((key.into(), value.serialize(NodeSerializer)?));
+    Ok(())
+  }
+
+  fn end(self) -> Result<Node> {
+    Ok(Node::Table(self.entries))
+  }
+}
+
+/// A variant's data: `variant(a, b)` for a tuple, dotted lines for a struct
+struct VariantSerializer<S>(&'static str, S);
+
+/// The value of a newtype variant, between its parentheses
+fn inline(node: &Node) -> Result<String> {
+  match node {
+    Node::Absent => Ok(String::new()),
+    Node::Value(value) => Ok(value.clone()),
+    Node::List(list) => join(list),
+    _ => unsupported("a nested variant"),
+  }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+  type Ok = Node;
+  type Error = Error;
+
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+    self.1.push(value)
+  }
+
+  fn end(self) -> Result<Node> {
+    Ok(Node::Value(format!("{}({})", self.0, join(&(self.1).0)?)))
+  }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<TableSerializer> {
+  type Ok = Node;
+  type Error = Error;
+
+  fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+    ser::SerializeStruct::serialize_field(&mut self.1, key, value)
+  }
+
+  fn end(self) -> Result<Node> {
+    Ok(Node::Variant(self.0.into(), Box::new(Node::Table(self.1.entries))))
+  }
+}
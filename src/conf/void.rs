@@ -12,7 +12,7 @@ pub fn unreachable(x: Void) -> ! {
 
 impl Clone for Void {
   fn clone(&self) -> Self {
-    unreachable(*self)
+    *self
   }
 }
 
@@ -40,30 +40,3 @@ impl<T> cmp::PartialOrd<T> for Void {
 }
 
 impl error::Error for Void {}
-
-pub trait ResultVoidExt<T>: Sized {
-  fn void_unwrap(self) -> T;
-}
-
-impl<T> ResultVoidExt<T> for Result<T, Void> {
-  #[inline]
-  fn void_unwrap(self) -> T {
-    match self {
-      Ok(val) => val,
-      Err(e) => unreachable(e),
-    }
-  }
-}
-pub trait ResultVoidErrExt<E>: Sized {
-  fn void_unwrap_err(self) -> E;
-}
-
-impl<E> ResultVoidErrExt<E> for Result<Void, E> {
-  #[inline]
-  fn void_unwrap_err(self) -> E {
-    match self {
-      Ok(val) => unreachable(val),
-      Err(e) => e,
-    }
-  }
-}
@@ -1,13 +1,23 @@
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Read, Write};
+use std::net::IpAddr;
+use std::str::FromStr;
 
-use super::conf::{self, Error};
+use super::builder::ConfigBuilder;
+use super::conf::{self, NextExt, Strictness};
+use super::err::FtpdError;
+use super::schema;
+use super::types::{ByteRate, Duration, IpNet, SiteCommand, Umask};
+use serde::Deserialize;
 
 use super::defaults::*;
-use serde_derive::Deserialize as De;
+use serde_derive::{Deserialize as De, Serialize as Ser};
 
-#[derive(Debug, De, Clone)]
+/// Environment variables starting with this override config keys
+pub const ENV_PREFIX: &str = "FTPD_";
+
+#[derive(Debug, De, Ser, Clone)]
 pub struct Config {
   /// Passive mode
   #[serde(default = "pasv_enable_default")]
@@ -26,12 +36,18 @@ pub struct Config {
   /// 777 - 077
   /// folders 700
   #[serde(default = "local_umask_default")]
-  pub local_umask: String,
+  pub local_umask: Umask,
 
   /// default 21
   #[serde(default = "listen_port_default")]
   pub listen_port: u32,
 
+  /// 被动模式端口范围, 0 表示由系统分配
+  #[serde(default)]
+  pub pasv_min_port: u32,
+  #[serde(default)]
+  pub pasv_max_port: u32,
+
   /// 最大客户端数
   #[serde(default = "max_clients_default")]
   pub max_clients: u32,
@@ -42,35 +58,382 @@ pub struct Config {
 
   /// pasv 模式下超时
   #[serde(default = "accept_timeout_default")]
-  pub accept_timeout: u32,
+  pub accept_timeout: Duration,
 
   /// 主动模式下超时
   #[serde(default = "connect_timeout_default")]
-  pub connect_timeout: u32,
+  pub connect_timeout: Duration,
 
   /// 无动作自动超时
   #[serde(default = "idle_session_timeout_default")]
-  pub idle_session_timeout: u32,
+  pub idle_session_timeout: Duration,
 
   /// 数据通道空闲超时
   #[serde(default = "data_connection_timeout_default")]
-  pub data_connection_timeout: u32,
+  pub data_connection_timeout: Duration,
 
   /// 上传最大速
   #[serde(default = "upload_max_rate_default")]
-  pub upload_max_rate: u32,
+  pub upload_max_rate: ByteRate,
 
   /// 下载最大速
   #[serde(default = "download_max_rate_default")]
-  pub download_max_rate: u32,
+  pub download_max_rate: ByteRate,
+
+  /// Allowed `SITE` subcommands
+  #[serde(default = "site_commands_default")]
+  pub site_commands: Vec<SiteCommand>,
+
+  /// `[user:name]` sections
+  #[serde(default)]
+  pub user: HashMap<String, Overrides>,
+
+  /// `[ip:10.0.0.0/8]` sections
+  #[serde(default)]
+  pub ip: HashMap<IpNet, Overrides>,
+}
+
+/// The settings a `[user:...]` or `[ip:...]` section may change for a session.
+#[derive(Debug, De, Ser, Clone, Default)]
+pub struct Overrides {
+  pub pasv_enable: Option<bool>,
+  pub port_enable: Option<bool>,
+  pub local_umask: Option<Umask>,
+  pub max_per_ip: Option<u32>,
+  pub accept_timeout: Option<Duration>,
+  pub connect_timeout: Option<Duration>,
+  pub idle_session_timeout: Option<Duration>,
+  pub data_connection_timeout: Option<Duration>,
+  pub upload_max_rate: Option<ByteRate>,
+  pub download_max_rate: Option<ByteRate>,
+  pub site_commands: Option<Vec<SiteCommand>>,
+}
+
+impl Default for Config {
+  /// The values from `defaults.rs`, the same as an empty file
+  fn default() -> Config {
+    Config {
+      pasv_enable: pasv_enable_default(),
+      port_enable: port_enable_default(),
+      listen_address: None,
+      local_umask: local_umask_default(),
+      listen_port: listen_port_default(),
+      pasv_min_port: 0,
+      pasv_max_port: 0,
+      max_clients: max_clients_default(),
+      max_per_ip: max_per_ip_default(),
+      accept_timeout: accept_timeout_default(),
+      connect_timeout: connect_timeout_default(),
+      idle_session_timeout: idle_session_timeout_default(),
+      data_connection_timeout: data_connection_timeout_default(),
+      upload_max_rate: upload_max_rate_default(),
+      download_max_rate: download_max_rate_default(),
+      site_commands: site_commands_default(),
+      user: HashMap::new(),
+      ip: HashMap::new(),
+    }
+  }
+}
+
+/// Lists every key of `Config` once: `scope name: type;` under the one line
+/// description `write_reference` and the schema show, optionally with
+/// `, schema {...}` to narrow the JSON Schema of its type. `server` keys are
+/// set at the top level only, `session` keys in `[user:...]` and `[ip:...]`
+/// sections as well, and `sections` are the sections themselves. From this
+/// come the key and doc lists, `set`, `apply` and the `ConfigBuilder` setters.
+macro_rules! config_keys {
+  (@sort [$($keys:tt)*] [$($session:tt)*] [$($sections:tt)*]) => {
+    config_keys!(@emit [$($keys)*] [$($session)*] [$($sections)*]);
+  };
+  (@sort [$($keys:tt)*] [$($session:tt)*] [$($sections:tt)*]
+    #[doc = $doc:literal]
+    server $field:ident: $ty:ty $(, schema $schema:tt)?;
+    $($rest:tt)*
+  ) => {
+    config_keys!(@sort
+      [$($keys)* {$doc $field: $ty, [$($schema)?]}]
+      [$($session)*]
+      [$($sections)*]
+      $($rest)*);
+  };
+  (@sort [$($keys:tt)*] [$($session:tt)*] [$($sections:tt)*]
+    #[doc = $doc:literal]
+    session $field:ident: $ty:ty $(, schema $schema:tt)?;
+    $($rest:tt)*
+  ) => {
+    config_keys!(@sort
+      [$($keys)* {$doc $field: $ty, [$($schema)?]}]
+      [$($session)* $field]
+      [$($sections)*]
+      $($rest)*);
+  };
+  (@sort [$($keys:tt)*] [$($session:tt)*] [$($sections:tt)*]
+    #[doc = $doc:literal]
+    sections $field:ident;
+    $($rest:tt)*
+  ) => {
+    config_keys!(@sort
+      [$($keys)*]
+      [$($session)*]
+      [$($sections)* {$doc $field}]
+      $($rest)*);
+  };
+  (@emit
+    [$({$doc:literal $field:ident: $ty:ty, [$($schema:tt)?]})*]
+    [$($session:ident)*]
+    [$({$section_doc:literal $section:ident})*]
+  ) => {
+    impl Overrides {
+      /// The keys a section may set
+      pub const KEYS: &'static [&'static str] = &[$(stringify!($session)),*];
+    }
+
+    impl Config {
+      /// The top level keys that hold a single value, in file order
+      pub const KEYS: &'static [&'static str] = &[$(stringify!($field)),*];
+
+      /// One line descriptions written above each key in `write_reference`
+      pub const DOCS: &'static [(&'static str, &'static str)] = &[
+        $((stringify!($field), $doc.trim_ascii()),)*
+        $((stringify!($section), $section_doc.trim_ascii()),)*
+      ];
+
+      /// Sets one top level key from its text, as if it were written `key = value`.
+      pub fn set(&mut self, key: &str, value: &str) -> Result<(), conf::Error> {
+        match key {
+          $(stringify!($field) => self.$field = conf::from_value(value)?,)*
+          key => return Err(conf::Error::Custom(format!("unknown key `{}`", key))),
+        }
+        Ok(())
+      }
+
+      /// Copies one top level key from `other`
+      pub(crate) fn copy_key(&mut self, other: &Config, key: &str) {
+        match key {
+          $(stringify!($field) => self.$field = other.$field.clone(),)*
+          _ => {}
+        }
+      }
+
+      /// The JSON Schema of each top level key, without its doc and default
+      pub(crate) fn schemas() -> Vec<(&'static str, serde_json::Value)> {
+        vec![$((
+          stringify!($field),
+          schema::narrow(<$ty as schema::Describe>::describe(), &[$(serde_json::json!($schema))?]),
+        )),*]
+      }
+
+      fn apply(&mut self, overrides: &Overrides) {
+        $(if let Some(ref value) = overrides.$session {
+          self.$session = value.clone();
+        })*
+      }
+    }
+
+    /// A typed setter per top level key, e.g. `Config::builder().listen_port(2121)`
+    impl ConfigBuilder {
+      $(pub fn $field(self, value: $ty) -> ConfigBuilder {
+        self.with(stringify!($field), move |config| config.$field = value)
+      })*
+    }
+  };
+  ($($keys:tt)*) => {
+    config_keys!(@sort [] [] [] $($keys)*);
+  };
+}
+
+config_keys! {
+  /// Allow passive mode data connections (PASV)
+  session pasv_enable: bool;
+  /// Allow active mode data connections (PORT)
+  session port_enable: bool;
+  /// Address to listen on, e.g. 192.168.1.100, all addresses if unset
+  server listen_address: Option<String>,
+    schema { "anyOf": [{ "format": "ipv4" }, { "format": "ipv6" }] };
+  /// Octal umask for new files and folders, 077 gives folders 700
+  session local_umask: Umask;
+  /// Control connection port
+  server listen_port: u32, schema { "maximum": 65535 };
+  /// Lowest port for passive data connections, 0 for any
+  server pasv_min_port: u32, schema { "maximum": 65535 };
+  /// Highest port for passive data connections, 0 for any
+  server pasv_max_port: u32, schema { "maximum": 65535 };
+  /// Most clients connected at once
+  server max_clients: u32;
+  /// Most clients connected from one address
+  session max_per_ip: u32;
+  /// How long to wait for the client to connect in passive mode
+  session accept_timeout: Duration;
+  /// How long to wait to connect to the client in active mode
+  session connect_timeout: Duration;
+  /// Idle time before a session is closed
+  session idle_session_timeout: Duration;
+  /// Idle time before a data connection is closed
+  session data_connection_timeout: Duration;
+  /// Upload limit per session in bytes per second, 0 for none
+  session upload_max_rate: ByteRate;
+  /// Download limit per session in bytes per second, 0 for none
+  session download_max_rate: ByteRate;
+  /// SITE commands a session may run, any of chmod, umask, idle and help
+  session site_commands: Vec<SiteCommand>;
+  /// `[user:name]` sections override settings for one user
+  sections user;
+  /// `[ip:10.0.0.0/8]` sections override settings for an address block
+  sections ip;
 }
 
 impl Config {
-  pub fn new(path: &str) -> Result<Config, Error> {
-    let mut buffer = String::new();
-    let mut file = File::open(&Path::new(&path)).expect("Failed to open file");
-    file.read_to_string(&mut buffer).expect("Failed to read file");
-    let config = conf::from_str::<Config>(&buffer)?;
+  /// Builds a config in code, starting from the defaults
+  pub fn builder() -> ConfigBuilder {
+    ConfigBuilder::new()
+  }
+
+  /// Loads `path` and the files it includes.
+  pub fn new(path: &str) -> Result<Config, FtpdError> {
+    Config::deserialize_strict(Config::open(path)?)
+  }
+
+  /// A deserializer over `path` and its includes
+  pub(crate) fn open(path: &str) -> Result<conf::Deserializer<impl NextExt>, FtpdError> {
+    let de = conf::Deserializer::from_file(path)
+      .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+    Ok(de)
+  }
+
+  /// The settings for one session. Matching `[ip:...]` sections apply from the
+  /// widest block to the narrowest, then the `[user:...]` section on top.
+  pub fn for_session(&self, user: Option<&str>, ip: IpAddr) -> Config {
+    let mut nets: Vec<_> = self.ip.iter().filter(|(net, _)| net.contains(ip)).collect();
+    nets.sort_by_key(|(net, _)| net.prefix_len());
+    let users = user.and_then(|user| self.user.get(user));
+    let mut config = self.clone();
+    for overrides in nets.into_iter().map(|(_, o)| o).chain(users) {
+      config.apply(overrides);
+    }
+    config
+  }
+
+  /// Applies `FTPD_<KEY>` environment variables on top, e.g. `FTPD_LISTEN_PORT=2121`.
+  /// Variables that don't name a key are left alone.
+  pub fn apply_env(&mut self) -> Result<(), FtpdError> {
+    self.apply_vars(env::vars())
+  }
+
+  /// `apply_env` over any set of variables
+  pub fn apply_vars<I: IntoIterator<Item = (String, String)>>(
+    &mut self,
+    vars: I,
+  ) -> Result<(), FtpdError> {
+    for (var, value) in vars {
+      if let Some(key) = Config::env_key(&var) {
+        self
+          .set(key, &value)
+          .map_err(|e| conf::Error::Custom(format!("{}: {}", var, e)))?;
+      }
+    }
+    Ok(())
+  }
+
+  /// The key an `FTPD_<KEY>` variable overrides
+  pub(crate) fn env_key(var: &str) -> Option<&'static str> {
+    let key = var.strip_prefix(ENV_PREFIX)?.to_lowercase();
+    Config::KEYS.iter().find(|&&k| k == key).copied()
+  }
+
+  /// Checks the values against each other and returns every problem found,
+  /// including the ones a `[user:...]` or `[ip:...]` section causes. A section
+  /// that only inherits a top level problem doesn't report it again.
+  pub fn validate(&self) -> Result<(), FtpdError> {
+    let mut problems = Vec::new();
+    if self.listen_port > 65535 {
+      problems.push(format!("listen_port {} is above 65535", self.listen_port));
+    }
+    if let Some(ref address) = self.listen_address {
+      if address.parse::<IpAddr>().is_err() {
+        problems.push(format!("listen_address `{}` is not an IP address", address));
+      }
+    }
+    for &(key, port) in &[
+      ("pasv_min_port", self.pasv_min_port),
+      ("pasv_max_port", self.pasv_max_port),
+    ] {
+      if port > 65535 {
+        problems.push(format!("{} {} is above 65535", key, port));
+      }
+    }
+    if self.pasv_max_port != 0 && self.pasv_min_port > self.pasv_max_port {
+      problems.push(format!(
+        "pasv_min_port {} is above pasv_max_port {}",
+        self.pasv_min_port, self.pasv_max_port
+      ));
+    }
+    let top = self.session_problems();
+    problems.extend(top.iter().cloned());
+
+    let mut sections: Vec<_> = self
+      .user
+      .iter()
+      .map(|(name, o)| (format!("user:{}", name), o))
+      .chain(self.ip.iter().map(|(net, o)| (format!("ip:{}", net), o)))
+      .collect();
+    sections.sort_by(|a, b| a.0.cmp(&b.0));
+    for (name, overrides) in sections {
+      let mut config = self.clone();
+      config.apply(overrides);
+      let found = config
+        .session_problems()
+        .into_iter()
+        .filter(|p| !top.contains(p));
+      problems.extend(found.map(|problem| format!("[{}] {}", name, problem)));
+    }
+
+    if problems.is_empty() {
+      Ok(())
+    } else {
+      Err(FtpdError::Validation(problems))
+    }
+  }
+
+  /// The problems a section can cause as well
+  fn session_problems(&self) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !self.pasv_enable && !self.port_enable {
+      problems.push("pasv_enable and port_enable are both off, no transfer can start".into());
+    }
+    if self.max_per_ip > self.max_clients {
+      problems
+        .push(format!("max_per_ip {} is above max_clients {}", self.max_per_ip, self.max_clients));
+    }
+    problems
+  }
+
+  /// Writes the config with each key's doc and default above it.
+  pub fn write_reference<W: Write>(&self, writer: W) -> Result<(), conf::Error> {
+    conf::Serializer::new(writer)
+      .docs(Config::DOCS)
+      .defaults(&Config::default())?
+      .serialize(self)
+  }
+
+  pub fn from_reader<R: Read>(reader: R) -> Result<Config, FtpdError> {
+    Config::deserialize_strict(conf::Deserializer::from_read(reader))
+  }
+
+  /// Unknown and repeated keys are rejected and the result is validated, use
+  /// `conf::from_str` and friends to load a config leniently.
+  fn deserialize_strict<T: NextExt>(de: conf::Deserializer<T>) -> Result<Config, FtpdError> {
+    let mut de = de.strictness(Strictness::Strict);
+    let config = Config::deserialize(&mut de)?;
+    de.assert_eof()?;
+    config.validate()?;
     Ok(config)
   }
 }
+
+impl FromStr for Config {
+  type Err = FtpdError;
+
+  fn from_str(s: &str) -> Result<Config, FtpdError> {
+    Config::deserialize_strict(conf::Deserializer::from_str(s))
+  }
+}
@@ -1,3 +1,5 @@
+use super::types::{ByteRate, Duration, SiteCommand, Umask};
+
 pub fn pasv_enable_default() -> bool {
   true
 }
@@ -6,8 +8,8 @@ pub fn port_enable_default() -> bool {
   true
 }
 
-pub fn local_umask_default() -> String {
-  "077".into()
+pub fn local_umask_default() -> Umask {
+  Umask::new(0o077).unwrap()
 }
 
 pub fn listen_port_default() -> u32 {
@@ -22,26 +24,30 @@ pub fn max_per_ip_default() -> u32 {
   2
 }
 
-pub fn accept_timeout_default() -> u32 {
-  60
+pub fn accept_timeout_default() -> Duration {
+  Duration::from_secs(60)
+}
+
+pub fn connect_timeout_default() -> Duration {
+  Duration::from_secs(100)
 }
 
-pub fn connect_timeout_default() -> u32 {
-  100
+pub fn idle_session_timeout_default() -> Duration {
+  Duration::from_secs(300)
 }
 
-pub fn idle_session_timeout_default() -> u32 {
-  300
+pub fn data_connection_timeout_default() -> Duration {
+  Duration::from_secs(900)
 }
 
-pub fn data_connection_timeout_default() -> u32 {
-  900
+pub fn upload_max_rate_default() -> ByteRate {
+  ByteRate::from_bytes(102400)
 }
 
-pub fn upload_max_rate_default() -> u32 {
-  102400
+pub fn download_max_rate_default() -> ByteRate {
+  ByteRate::from_bytes(204800)
 }
 
-pub fn download_max_rate_default() -> u32 {
-  204800
+pub fn site_commands_default() -> Vec<SiteCommand> {
+  SiteCommand::ALL.to_vec()
 }
@@ -1,8 +1,35 @@
+use std::fmt::{self, Display, Formatter};
 use std::io::Error;
 
+use super::conf;
+
 #[derive(Debug)]
 pub enum FtpdError {
   Io(Error),
+  /// The config could not be parsed or deserialized
+  Syntax(conf::Error),
+  /// The config parsed but its values don't make sense
+  Validation(Vec<String>),
+}
+
+impl Display for FtpdError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      FtpdError::Io(e) => write!(f, "{}", e),
+      FtpdError::Syntax(e) => write!(f, "{}", e),
+      FtpdError::Validation(problems) => write!(f, "invalid config: {}", problems.join("; ")),
+    }
+  }
+}
+
+impl std::error::Error for FtpdError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      FtpdError::Io(e) => Some(e),
+      FtpdError::Syntax(e) => Some(e),
+      FtpdError::Validation(_) => None,
+    }
+  }
 }
 
 impl From<Error> for FtpdError {
@@ -10,3 +37,9 @@ impl From<Error> for FtpdError {
     FtpdError::Io(err)
   }
 }
+
+impl From<conf::Error> for FtpdError {
+  fn from(err: conf::Error) -> Self {
+    FtpdError::Syntax(err)
+  }
+}
@@ -2,9 +2,19 @@
 extern crate serde;
 extern crate serde_derive;
 
-mod err;
-mod defaults;
-pub mod status;
+pub mod abort;
+pub mod builder;
 pub mod conf;
 pub mod config;
+mod defaults;
+mod err;
+pub mod reload;
+pub mod schema;
+pub mod site;
+pub mod stat;
+pub mod status;
+pub mod transfer;
+pub mod types;
+pub mod vsftpd;
 
+pub use err::FtpdError;
@@ -0,0 +1,78 @@
+use std::env;
+use std::io;
+use std::process;
+use std::sync::Arc;
+
+use ftpd::builder::ConfigBuilder;
+use ftpd::conf;
+use ftpd::reload::SharedConfig;
+use ftpd::FtpdError;
+
+const USAGE: &str = "usage: ftpd [--print-schema] [--show-config] [CONFIG]";
+
+/// Config read when no path is given
+const DEFAULT_CONFIG: &str = "/etc/ftpd.conf";
+
+fn main() {
+  let mut path = None;
+  let mut show_config = false;
+  for arg in env::args().skip(1) {
+    match &*arg {
+      "--print-schema" => {
+        println!("{:#}", ftpd::schema::json_schema());
+        return;
+      }
+      "--show-config" => show_config = true,
+      "-h" | "--help" => {
+        println!("{}", USAGE);
+        return;
+      }
+      flag if flag.starts_with('-') => {
+        eprintln!("ftpd: unknown option `{}`\n{}", flag, USAGE);
+        process::exit(2);
+      }
+      _ => path = Some(arg),
+    }
+  }
+  let path = path.unwrap_or_else(|| DEFAULT_CONFIG.into());
+  let result = if show_config {
+    print_config(&path)
+  } else {
+    run(&path)
+  };
+  if let Err(e) = result {
+    eprintln!("ftpd: {}", e);
+    process::exit(1);
+  }
+}
+
+/// Loads the config and keeps it current, reloading it on SIGHUP until the
+/// process is stopped
+fn run(path: &str) -> Result<(), FtpdError> {
+  let shared = Arc::new(SharedConfig::load(path)?);
+  #[cfg(unix)]
+  shared
+    .reload_on_sighup()?
+    .join()
+    .map_err(|_| io::Error::other("the config reload thread panicked"))?;
+  #[cfg(not(unix))]
+  drop(shared);
+  Ok(())
+}
+
+/// Prints the merged config with the layer each key came from above it
+fn print_config(path: &str) -> Result<(), FtpdError> {
+  let (config, provenance) = ConfigBuilder::new()
+    .file(path)
+    .env()
+    .build_with_provenance()?;
+  let notes: Vec<_> = provenance
+    .iter()
+    .map(|(key, source)| (key, format!("from {}", source)))
+    .collect();
+  let notes: Vec<_> = notes.iter().map(|(key, note)| (*key, &**note)).collect();
+  conf::Serializer::new(io::stdout())
+    .docs(&notes)
+    .serialize(&config)?;
+  Ok(())
+}
@@ -0,0 +1,72 @@
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+#[cfg(unix)]
+use std::{
+  io,
+  thread::{self, JoinHandle},
+};
+
+#[cfg(unix)]
+use signal_hook::{consts::SIGHUP, iterator::Signals};
+
+use super::builder::ConfigBuilder;
+use super::config::Config;
+use super::err::FtpdError;
+
+/// The config new sessions start from. A reload replaces it as a whole, so a
+/// session holding the `Arc` from `get` keeps the settings it started with.
+pub struct SharedConfig {
+  path: String,
+  current: RwLock<Arc<Config>>,
+}
+
+impl SharedConfig {
+  /// Loads `path` with the `FTPD_` environment overrides on top.
+  pub fn load(path: &str) -> Result<SharedConfig, FtpdError> {
+    Ok(SharedConfig {
+      path: path.into(),
+      current: RwLock::new(Arc::new(SharedConfig::read(path)?)),
+    })
+  }
+
+  pub fn get(&self) -> Arc<Config> {
+    self.current.read().unwrap().clone()
+  }
+
+  /// Reads the file again and swaps it in if it is valid. On error the
+  /// running config is left as it was.
+  pub fn reload(&self) -> Result<(), FtpdError> {
+    let config = SharedConfig::read(&self.path)?;
+    *self.current.write().unwrap() = Arc::new(config);
+    Ok(())
+  }
+
+  /// Copies the settings that are safe to change mid session, the rate limits
+  /// and the data connection timeout, from the current config into a session's
+  /// own. `idle_session_timeout` is left alone since `SITE IDLE` may have set it.
+  pub fn refresh(&self, session: &mut Config, user: Option<&str>, ip: IpAddr) {
+    let latest = self.get().for_session(user, ip);
+    session.upload_max_rate = latest.upload_max_rate;
+    session.download_max_rate = latest.download_max_rate;
+    session.data_connection_timeout = latest.data_connection_timeout;
+  }
+
+  /// Reloads on every SIGHUP from a background thread. A rejected reload is
+  /// reported on stderr and the server carries on with the old config.
+  #[cfg(unix)]
+  pub fn reload_on_sighup(self: &Arc<Self>) -> io::Result<JoinHandle<()>> {
+    let mut signals = Signals::new([SIGHUP])?;
+    let shared = Arc::clone(self);
+    Ok(thread::spawn(move || {
+      for _ in signals.forever() {
+        if let Err(e) = shared.reload() {
+          eprintln!("ftpd: keeping the running config, reload of {} failed: {}", shared.path, e);
+        }
+      }
+    }))
+  }
+
+  fn read(path: &str) -> Result<Config, FtpdError> {
+    ConfigBuilder::new().file(path).env().build()
+  }
+}
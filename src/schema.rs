@@ -0,0 +1,172 @@
+use serde_json::{json, Map, Value};
+
+use super::config::{Config, Overrides};
+use super::types::{ByteRate, Duration, IpNet, SiteCommand, Umask};
+
+/// A JSON Schema (draft 7) of the config with every key's type, default,
+/// description and allowed range, for checking a config before it is deployed.
+/// Each key is described the way it is written in the file, so the typed
+/// values allow both plain numbers and their unit forms.
+pub fn json_schema() -> Value {
+  let defaults = serde_json::to_value(Config::default()).expect("a config serializes to JSON");
+  let schemas = Config::schemas();
+  let properties = |keys: &[&str], with_defaults: bool| {
+    let mut properties = Map::new();
+    for (key, schema) in schemas.iter().filter(|(key, _)| keys.contains(key)) {
+      let mut schema = schema.clone();
+      if let Some(doc) = doc(key) {
+        schema["description"] = doc.into();
+      }
+      match defaults.get(key) {
+        Some(default) if with_defaults && !default.is_null() => schema["default"] = default.clone(),
+        _ => {}
+      }
+      properties.insert((*key).into(), schema);
+    }
+    Value::Object(properties)
+  };
+  let overrides = json!({
+    "type": "object",
+    "properties": properties(Overrides::KEYS, false),
+    "additionalProperties": false,
+  });
+  let mut config = properties(Config::KEYS, true);
+  config["user"] = json!({
+    "description": doc("user"),
+    "type": "object",
+    "additionalProperties": { "$ref": "#/definitions/overrides" },
+  });
+  config["ip"] = json!({
+    "description": doc("ip"),
+    "type": "object",
+    "propertyNames": IpNet::describe(),
+    "additionalProperties": { "$ref": "#/definitions/overrides" },
+  });
+  config["include"] = json!({
+    "description": "Files to read in place of the `include` line, relative to the including file, glob patterns allowed",
+    "anyOf": [
+      { "type": "string" },
+      { "type": "array", "items": { "type": "string" } },
+    ],
+  });
+  json!({
+    "$schema": "http://json-schema.org/draft-07/schema#",
+    "title": "ftpd.conf",
+    "type": "object",
+    "properties": config,
+    "additionalProperties": false,
+    "definitions": { "overrides": overrides },
+  })
+}
+
+/// The one line description of a key
+fn doc(key: &str) -> Option<&'static str> {
+  Config::DOCS
+    .iter()
+    .find(|(k, _)| *k == key)
+    .map(|&(_, doc)| doc)
+}
+
+/// The schema of a value as it is written in the file
+pub(crate) trait Describe {
+  fn describe() -> Value;
+}
+
+/// `schema` with the fields of each of `extra` added or replaced
+pub(crate) fn narrow(mut schema: Value, extra: &[Value]) -> Value {
+  for (key, value) in extra.iter().filter_map(Value::as_object).flatten() {
+    schema[key] = value.clone();
+  }
+  schema
+}
+
+/// A number, or the text `FromStr` reads with a unit
+fn with_unit(pattern: &str) -> Value {
+  json!({
+    "anyOf": [
+      { "type": "integer", "minimum": 0 },
+      { "type": "string", "pattern": pattern },
+    ]
+  })
+}
+
+/// The words a bool may be written as, in any case
+const BOOL_WORDS: &[&str] = &["true", "false", "yes", "no", "on", "off", "1", "0"];
+
+impl Describe for bool {
+  fn describe() -> Value {
+    let words: Vec<String> = BOOL_WORDS
+      .iter()
+      .map(|word| {
+        word
+          .chars()
+          .map(|c| match c {
+            'a'..='z' => format!("[{}{}]", c, c.to_ascii_uppercase()),
+            c => c.to_string(),
+          })
+          .collect()
+      })
+      .collect();
+    json!({
+      "anyOf": [
+        { "type": "boolean" },
+        { "type": "string", "pattern": format!("^({})$", words.join("|")) },
+      ]
+    })
+  }
+}
+
+impl Describe for u32 {
+  fn describe() -> Value {
+    json!({ "type": "integer", "minimum": 0, "maximum": u32::MAX })
+  }
+}
+
+impl Describe for String {
+  fn describe() -> Value {
+    json!({ "type": "string" })
+  }
+}
+
+impl<T: Describe> Describe for Option<T> {
+  fn describe() -> Value {
+    T::describe()
+  }
+}
+
+impl<T: Describe> Describe for Vec<T> {
+  fn describe() -> Value {
+    json!({ "type": "array", "items": T::describe() })
+  }
+}
+
+impl Describe for Umask {
+  fn describe() -> Value {
+    json!({ "type": "string", "pattern": "^0*[0-7]{1,3}$" })
+  }
+}
+
+impl Describe for Duration {
+  fn describe() -> Value {
+    with_unit("^[0-9]+[smhd]?$")
+  }
+}
+
+impl Describe for ByteRate {
+  fn describe() -> Value {
+    with_unit("^[0-9]+[kKmMgG]?$")
+  }
+}
+
+impl Describe for IpNet {
+  fn describe() -> Value {
+    json!({ "type": "string", "pattern": r"^[0-9a-fA-F.:]+(/[0-9]{1,3})?$" })
+  }
+}
+
+impl Describe for SiteCommand {
+  fn describe() -> Value {
+    let names: Vec<_> = SiteCommand::ALL.iter().map(|c| c.name()).collect();
+    json!({ "type": "string", "enum": names })
+  }
+}
@@ -0,0 +1,145 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::config::Config;
+use super::status::{BADCMD, BADOPTS, CHMODOK, FILEFAIL, IDLEOK, NOPERM, SITEHELP, UMASKOK};
+use super::types::{Duration, SiteCommand, Umask};
+
+/// A `SITE` command
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Site {
+  /// `SITE CHMOD <mode> <path>`, the mode in octal up to 777
+  Chmod { mode: u32, path: String },
+  /// `SITE UMASK [mask]`, without a mask the current one is reported
+  Umask(Option<Umask>),
+  /// `SITE IDLE [secs]`, without a value the current timeout is reported
+  Idle(Option<Duration>),
+  /// `SITE HELP`
+  Help,
+}
+
+impl Site {
+  /// Parses the argument of `SITE`, the error is the reply code to answer with.
+  /// `config` is the session's config before any `SITE` command changed it:
+  /// its `site_commands` says what may run and its `idle_session_timeout` is
+  /// the most `SITE IDLE` may ask for.
+  pub fn from_arg(arg: &str, config: &Config) -> Result<Site, u64> {
+    let arg = arg.trim();
+    let (name, rest) = match arg.split_once(char::is_whitespace) {
+      Some((name, rest)) => (name, rest.trim()),
+      None => (arg, ""),
+    };
+    let command: SiteCommand = name.parse().map_err(|_| BADCMD)?;
+    if !config.site_commands.contains(&command) {
+      return Err(NOPERM);
+    }
+    match (command, rest) {
+      (SiteCommand::Chmod, rest) => {
+        let (mode, path) = rest.split_once(char::is_whitespace).ok_or(BADOPTS)?;
+        let mode = u32::from_str_radix(mode, 8)
+          .ok()
+          .filter(|&bits| bits <= 0o777 && !mode.starts_with('+'))
+          .ok_or(BADOPTS)?;
+        Ok(Site::Chmod {
+          mode,
+          path: path.trim().into(),
+        })
+      }
+      (SiteCommand::Umask, "") => Ok(Site::Umask(None)),
+      (SiteCommand::Umask, mask) => mask
+        .parse()
+        .map(|mask| Site::Umask(Some(mask)))
+        .map_err(|_| BADOPTS),
+      (SiteCommand::Idle, "") => Ok(Site::Idle(None)),
+      (SiteCommand::Idle, secs) => match secs.parse() {
+        Ok(secs) if secs > 0 && secs <= config.idle_session_timeout.as_secs() => {
+          Ok(Site::Idle(Some(Duration::from_secs(secs))))
+        }
+        _ => Err(BADOPTS),
+      },
+      (SiteCommand::Help, _) => Ok(Site::Help),
+    }
+  }
+
+  /// The code of the reply once the command has been carried out
+  pub fn code(&self) -> u64 {
+    match self {
+      Site::Chmod { .. } => CHMODOK,
+      Site::Umask(_) => UMASKOK,
+      Site::Idle(_) => IDLEOK,
+      Site::Help => SITEHELP,
+    }
+  }
+
+  /// Sets the mode of the `SITE CHMOD` file, its path taken from `root`, the
+  /// session's root directory. `FILEFAIL` if the file can't be changed, other
+  /// commands do nothing here.
+  pub fn chmod(&self, root: &Path) -> Result<(), u64> {
+    match self {
+      Site::Chmod { mode, path } => set_mode(&under(root, path), *mode).map_err(|_| FILEFAIL),
+      _ => Ok(()),
+    }
+  }
+
+  /// Sets the session's umask or idle timeout, CHMOD is done by `chmod`.
+  pub fn apply(&self, session: &mut Config) {
+    match *self {
+      Site::Umask(Some(mask)) => session.local_umask = mask,
+      Site::Idle(Some(timeout)) => session.idle_session_timeout = timeout,
+      _ => {}
+    }
+  }
+
+  /// The text of the reply, from the session after `apply`
+  pub fn message(&self, session: &Config) -> String {
+    match self {
+      Site::Chmod { .. } => "SITE CHMOD command ok.".into(),
+      Site::Umask(None) => format!("Your current UMASK is {}", session.local_umask),
+      Site::Umask(Some(mask)) => format!("UMASK set to {}", mask),
+      Site::Idle(None) => {
+        format!("Current idle time is {} seconds", session.idle_session_timeout.as_secs())
+      }
+      Site::Idle(Some(timeout)) => format!("Idle time set to {} seconds", timeout.as_secs()),
+      Site::Help => help(session),
+    }
+  }
+}
+
+/// `path` below `root`, with `..` stopping at the root so it can't be left
+pub(crate) fn under(root: &Path, path: &str) -> PathBuf {
+  let mut inside = PathBuf::new();
+  for component in Path::new(path).components() {
+    match component {
+      Component::Normal(name) => inside.push(name),
+      Component::ParentDir => {
+        inside.pop();
+      }
+      _ => {}
+    }
+  }
+  root.join(inside)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+  fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+/// Only the write bits mean anything without Unix modes
+#[cfg(not(unix))]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+  let mut permissions = fs::metadata(path)?.permissions();
+  permissions.set_readonly(mode & 0o222 == 0);
+  fs::set_permissions(path, permissions)
+}
+
+/// The `SITE HELP` text, the commands the session may run
+pub fn help(config: &Config) -> String {
+  let names: Vec<_> = SiteCommand::ALL
+    .iter()
+    .filter(|command| config.site_commands.contains(command))
+    .map(|command| command.name().to_uppercase())
+    .collect();
+  names.join(" ")
+}
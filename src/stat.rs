@@ -0,0 +1,172 @@
+use std::fs::{self, Metadata};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::config::Config;
+use super::site;
+use super::status::{self, FILEFAIL, STATFILEOK, STATOK};
+use super::transfer::{Mode, TransferType};
+use super::types::ByteRate;
+
+const MONTHS: [&str; 12] = [
+  "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Files changed longer ago than this are listed with their year, not the time
+const RECENT_SECS: u64 = 180 * 24 * 60 * 60;
+
+/// The reply to `STAT` without an argument: who the session is, its transfer
+/// settings, and the rate limits and timeouts it runs with after the
+/// `[ip:...]` and `[user:...]` sections for it are applied.
+pub fn server_status(
+  config: &Config,
+  user: Option<&str>,
+  peer: IpAddr,
+  transfer_type: TransferType,
+  mode: Mode,
+) -> String {
+  let session = config.for_session(user, peer);
+  let transfer_type = match transfer_type {
+    TransferType::Ascii => "ASCII",
+    TransferType::Image => "BINARY",
+  };
+  let mode = match mode {
+    Mode::Stream => "Stream".to_string(),
+    Mode::Zlib(level) => format!("Z, zlib level {}", level),
+  };
+  let lines = [
+    "FTP server status:".to_string(),
+    format!("     Connected to {}", peer),
+    match user {
+      Some(user) => format!("     Logged in as {}", user),
+      None => "     Not logged in".into(),
+    },
+    format!("     TYPE: {}, MODE: {}", transfer_type, mode),
+    rate_limit("Upload", session.upload_max_rate),
+    rate_limit("Download", session.download_max_rate),
+    format!("     Session timeout in seconds is {}", session.idle_session_timeout.as_secs()),
+    format!(
+      "     Data connection timeout in seconds is {}",
+      session.data_connection_timeout.as_secs()
+    ),
+    "End of status".into(),
+  ];
+  status::reply(STATOK, &lines)
+}
+
+fn rate_limit(direction: &str, rate: ByteRate) -> String {
+  if rate.is_unlimited() {
+    format!("     No {} rate limit", direction.to_lowercase())
+  } else {
+    format!("     {} rate limit is {} bytes per second", direction, rate)
+  }
+}
+
+/// The reply to `STAT <path>`: the `ls -l` style listing of a directory's
+/// entries in name order, or of a single file, sent on the control channel.
+/// `path` is taken below `root`, the session's root directory, and `FILEFAIL`
+/// is the reply code when it can't be read.
+pub fn file_status(root: &Path, path: &str) -> Result<String, u64> {
+  let target = site::under(root, path);
+  let metadata = fs::symlink_metadata(&target).map_err(|_| FILEFAIL)?;
+  let now = SystemTime::now();
+  let mut lines = vec![format!("Status of {}:", path)];
+  if metadata.is_dir() {
+    let mut entries = fs::read_dir(&target)
+      .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+      .map_err(|_| FILEFAIL)?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+      if let Ok(metadata) = entry.metadata() {
+        let name = entry.file_name();
+        lines.push(list_line(&metadata, &name.to_string_lossy(), now));
+      }
+    }
+  } else {
+    let name = target
+      .file_name()
+      .map_or_else(Default::default, |name| name.to_string_lossy());
+    lines.push(list_line(&metadata, &name, now));
+  }
+  lines.push("End of status".into());
+  Ok(status::reply(STATFILEOK, &lines))
+}
+
+/// One entry as `ls -l` shows it, e.g.
+/// `-rw-r--r-- 1 ftp ftp 1024 Mar 05 14:30 notes.txt`
+fn list_line(metadata: &Metadata, name: &str, now: SystemTime) -> String {
+  let kind = if metadata.is_dir() {
+    'd'
+  } else if metadata.file_type().is_symlink() {
+    'l'
+  } else {
+    '-'
+  };
+  let mtime = metadata.modified().unwrap_or(UNIX_EPOCH);
+  format!(
+    "{}{} 1 ftp ftp {:>8} {} {}",
+    kind,
+    permissions(metadata),
+    metadata.len(),
+    list_time(mtime, now),
+    name
+  )
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &Metadata) -> String {
+  use std::os::unix::fs::PermissionsExt;
+  let mode = metadata.permissions().mode();
+  (0..9)
+    .map(|i| match mode & (0o400 >> i) {
+      0 => '-',
+      _ => ['r', 'w', 'x'][i % 3],
+    })
+    .collect()
+}
+
+#[cfg(not(unix))]
+fn permissions(metadata: &Metadata) -> String {
+  match (metadata.is_dir(), metadata.permissions().readonly()) {
+    (true, _) => "rwxr-xr-x",
+    (false, true) => "r--r--r--",
+    (false, false) => "rw-r--r--",
+  }
+  .into()
+}
+
+/// `Mar 05 14:30` for the last six months, `Mar 05  2023` before that, in UTC
+fn list_time(time: SystemTime, now: SystemTime) -> String {
+  let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+  let (year, month, day) = civil_date(secs / 86400);
+  let recent = now
+    .duration_since(time)
+    .map_or(true, |age| age.as_secs() < RECENT_SECS);
+  if recent {
+    let minutes = secs % 86400 / 60;
+    format!("{} {:02} {:02}:{:02}", MONTHS[month - 1], day, minutes / 60, minutes % 60)
+  } else {
+    format!("{} {:02}  {}", MONTHS[month - 1], day, year)
+  }
+}
+
+/// Year, month and day of the `days`th day after 1970-01-01
+fn civil_date(days: u64) -> (u64, usize, u64) {
+  // Counted in 400 year eras from 0000-03-01, so leap days end each year
+  let days = days + 719_468;
+  let era = days / 146_097;
+  let day_of_era = days % 146_097;
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+  let month = if shifted_month < 10 {
+    shifted_month + 3
+  } else {
+    shifted_month - 9
+  };
+  let year = era * 400 + year_of_era + u64::from(month <= 2);
+  (year, month as usize, day)
+}
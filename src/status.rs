@@ -1,70 +1,86 @@
 #![allow(dead_code)]
 
-const DATACONN: u64 = 150;
-const NOOPOK: u64 = 200;
-const TYPEOK: u64 = 200;
-const PORTOK: u64 = 200;
-const EPRTOK: u64 = 200;
-const UMASKOK: u64 = 200;
-const CHMODOK: u64 = 200;
-const EPSVALLOK: u64 = 200;
-const STRUOK: u64 = 200;
-const MODEOK: u64 = 200;
-const PBSZOK: u64 = 200;
-const PROTOK: u64 = 200;
-const OPTSOK: u64 = 200;
-const ALLOOK: u64 = 202;
-const FEAT: u64 = 211;
-const STATOK: u64 = 211;
-const SIZEOK: u64 = 213;
-const MDTMOK: u64 = 213;
-const STATFILEOK: u64 = 213;
-const SITEHELP: u64 = 214;
-const HELP: u64 = 214;
-const SYSTOK: u64 = 215;
-const GREET: u64 = 220;
-const GOODBYE: u64 = 221;
-const ABORNOCONN: u64 = 225;
-const TRANSFEROK: u64 = 226;
-const ABOROK: u64 = 226;
-const PASVOK: u64 = 227;
-const EPSVOK: u64 = 229;
-const LOGINOK: u64 = 230;
-const AUTHOK: u64 = 234;
-const CWDOK: u64 = 250;
-const RMDIROK: u64 = 250;
-const DELEOK: u64 = 250;
-const RENAMEOK: u64 = 250;
-const PWDOK: u64 = 257;
-const MKDIROK: u64 = 257;
-const GIVEPWORD: u64 = 331;
-const RESTOK: u64 = 350;
-const RNFROK: u64 = 350;
-const IDLETIMEOUT: u64 = 421;
-const DATATIMEOUT: u64 = 421;
-const TOOMANYUSERS: u64 = 421;
-const IPLIMIT: u64 = 421;
-const IPDENY: u64 = 421;
-const TLSFAIL: u64 = 421;
-const BADSENDCONN: u64 = 425;
-const BADSENDNET: u64 = 426;
-const BADSENDFILE: u64 = 451;
-const BADCMD: u64 = 500;
-const BADOPTS: u64 = 501;
-const COMMANDNOTIMPL: u64 = 502;
-const NEEDUSER: u64 = 503;
-const NEEDRNFR: u64 = 503;
-const BADPBSZ: u64 = 503;
-const BADPROT: u64 = 503;
-const BADSTRU: u64 = 504;
-const BADMODE: u64 = 504;
-const BADAUTH: u64 = 504;
-const NOSUCHPROT: u64 = 504;
-const NEEDENCRYPT: u64 = 522;
-const EPSVBAD: u64 = 522;
-const DATATLSBAD: u64 = 522;
-const LOGINERR: u64 = 530;
-const NOHANDLEPROT: u64 = 536;
-const FILEFAIL: u64 = 550;
-const NOPERM: u64 = 550;
-const UPLOADFAIL: u64 = 553;
+pub const DATACONN: u64 = 150;
+pub const NOOPOK: u64 = 200;
+pub const TYPEOK: u64 = 200;
+pub const PORTOK: u64 = 200;
+pub const EPRTOK: u64 = 200;
+pub const UMASKOK: u64 = 200;
+pub const CHMODOK: u64 = 200;
+pub const IDLEOK: u64 = 200;
+pub const EPSVALLOK: u64 = 200;
+pub const STRUOK: u64 = 200;
+pub const MODEOK: u64 = 200;
+pub const PBSZOK: u64 = 200;
+pub const PROTOK: u64 = 200;
+pub const OPTSOK: u64 = 200;
+pub const ALLOOK: u64 = 202;
+pub const FEAT: u64 = 211;
+pub const STATOK: u64 = 211;
+pub const SIZEOK: u64 = 213;
+pub const MDTMOK: u64 = 213;
+pub const STATFILEOK: u64 = 213;
+pub const SITEHELP: u64 = 214;
+pub const HELP: u64 = 214;
+pub const SYSTOK: u64 = 215;
+pub const GREET: u64 = 220;
+pub const GOODBYE: u64 = 221;
+pub const ABORNOCONN: u64 = 225;
+pub const TRANSFEROK: u64 = 226;
+pub const ABOROK: u64 = 226;
+pub const PASVOK: u64 = 227;
+pub const EPSVOK: u64 = 229;
+pub const LOGINOK: u64 = 230;
+pub const AUTHOK: u64 = 234;
+pub const CWDOK: u64 = 250;
+pub const RMDIROK: u64 = 250;
+pub const DELEOK: u64 = 250;
+pub const RENAMEOK: u64 = 250;
+pub const PWDOK: u64 = 257;
+pub const MKDIROK: u64 = 257;
+pub const GIVEPWORD: u64 = 331;
+pub const RESTOK: u64 = 350;
+pub const RNFROK: u64 = 350;
+pub const IDLETIMEOUT: u64 = 421;
+pub const DATATIMEOUT: u64 = 421;
+pub const TOOMANYUSERS: u64 = 421;
+pub const IPLIMIT: u64 = 421;
+pub const IPDENY: u64 = 421;
+pub const TLSFAIL: u64 = 421;
+pub const BADSENDCONN: u64 = 425;
+pub const BADSENDNET: u64 = 426;
+pub const BADSENDFILE: u64 = 451;
+pub const BADCMD: u64 = 500;
+pub const BADOPTS: u64 = 501;
+pub const COMMANDNOTIMPL: u64 = 502;
+pub const NEEDUSER: u64 = 503;
+pub const NEEDRNFR: u64 = 503;
+pub const BADPBSZ: u64 = 503;
+pub const BADPROT: u64 = 503;
+pub const BADSTRU: u64 = 504;
+pub const BADMODE: u64 = 504;
+pub const BADAUTH: u64 = 504;
+pub const NOSUCHPROT: u64 = 504;
+pub const NEEDENCRYPT: u64 = 522;
+pub const EPSVBAD: u64 = 522;
+pub const DATATLSBAD: u64 = 522;
+pub const LOGINERR: u64 = 530;
+pub const NOHANDLEPROT: u64 = 536;
+pub const FILEFAIL: u64 = 550;
+pub const NOPERM: u64 = 550;
+pub const UPLOADFAIL: u64 = 553;
+
+/// A reply as sent on the control channel, `code-` before the first of several
+/// lines and `code ` before the last
+pub fn reply<S: AsRef<str>>(code: u64, lines: &[S]) -> String {
+  let mut reply = String::new();
+  let last = lines.len().saturating_sub(1);
+  for (i, line) in lines.iter().enumerate() {
+    match i {
+      0 if last > 0 => reply.push_str(&format!("{}-{}\r\n", code, line.as_ref())),
+      i if i == last => reply.push_str(&format!("{} {}\r\n", code, line.as_ref())),
+      _ => reply.push_str(&format!("{}\r\n", line.as_ref())),
+    }
+  }
+  reply
+}
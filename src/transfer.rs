@@ -0,0 +1,251 @@
+use std::io::{self, Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use super::status::{BADMODE, BADOPTS, BADSTRU};
+
+/// Lines this module adds to the FEAT reply
+pub const FEATURES: &[&str] = &["MODE Z"];
+
+/// zlib level used until `OPTS MODE Z LEVEL n` says otherwise
+pub const ZLIB_LEVEL_DEFAULT: u32 = 6;
+
+/// TYPE
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum TransferType {
+  /// TYPE A, line endings are converted on the fly
+  #[default]
+  Ascii,
+  /// TYPE I / TYPE L 8
+  Image,
+}
+
+impl TransferType {
+  /// Parses the argument of `TYPE`, the error is the reply code to answer with.
+  /// A type or format other than the ones above is answered with `BADMODE`.
+  pub fn from_arg(arg: &str) -> Result<TransferType, u64> {
+    let mut args = arg.split_whitespace().map(str::to_uppercase);
+    let kind = args.next().ok_or(BADOPTS)?;
+    let param = args.next();
+    match (&*kind, param.as_deref()) {
+      ("A", None) | ("A", Some("N")) => Ok(TransferType::Ascii),
+      ("I", None) | ("L", Some("8")) => Ok(TransferType::Image),
+      _ => Err(BADMODE),
+    }
+  }
+}
+
+/// MODE
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Mode {
+  /// MODE S
+  #[default]
+  Stream,
+  /// MODE Z, the stream is zlib compressed at the given level
+  Zlib(u32),
+}
+
+impl Mode {
+  /// Parses the argument of `MODE`, the error is the reply code to answer with.
+  /// `zlib_level` is the session's level, `ZLIB_LEVEL_DEFAULT` until an
+  /// `OPTS MODE Z LEVEL n` changed it, so switching modes keeps it.
+  pub fn from_arg(arg: &str, zlib_level: u32) -> Result<Mode, u64> {
+    match &*arg.trim().to_uppercase() {
+      "S" => Ok(Mode::Stream),
+      "Z" => Ok(Mode::Zlib(zlib_level)),
+      "" => Err(BADOPTS),
+      _ => Err(BADMODE),
+    }
+  }
+
+  /// Parses the argument of `OPTS MODE Z LEVEL n` into the new level.
+  pub fn zlib_level_from_opts(arg: &str) -> Result<u32, u64> {
+    let args: Vec<_> = arg.split_whitespace().map(str::to_uppercase).collect();
+    match &*args {
+      [mode, z, level, n] if mode == "MODE" && z == "Z" && level == "LEVEL" => match n.parse() {
+        Ok(n) if n <= 9 => Ok(n),
+        _ => Err(BADOPTS),
+      },
+      _ => Err(BADOPTS),
+    }
+  }
+
+  /// Wraps the data connection when sending, call `finish` on a compressed stream
+  /// so the trailer is written before the socket is closed.
+  pub fn sender<W: Write>(self, writer: W) -> Sender<W> {
+    match self {
+      Mode::Stream => Sender::Stream(writer),
+      Mode::Zlib(level) => Sender::Zlib(ZlibEncoder::new(writer, Compression::new(level))),
+    }
+  }
+
+  /// Wraps the data connection when receiving.
+  pub fn receiver<R: Read>(self, reader: R) -> Receiver<R> {
+    match self {
+      Mode::Stream => Receiver::Stream(reader),
+      Mode::Zlib(_) => Receiver::Zlib(ZlibDecoder::new(reader)),
+    }
+  }
+}
+
+pub enum Sender<W: Write> {
+  Stream(W),
+  Zlib(ZlibEncoder<W>),
+}
+
+impl<W: Write> Sender<W> {
+  pub fn finish(self) -> io::Result<W> {
+    match self {
+      Sender::Stream(mut w) => w.flush().map(|_| w),
+      Sender::Zlib(z) => z.finish(),
+    }
+  }
+}
+
+impl<W: Write> Write for Sender<W> {
+  fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+    match self {
+      Sender::Stream(w) => w.write(data),
+      Sender::Zlib(z) => z.write(data),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      Sender::Stream(w) => w.flush(),
+      Sender::Zlib(z) => z.flush(),
+    }
+  }
+}
+
+pub enum Receiver<R: Read> {
+  Stream(R),
+  Zlib(ZlibDecoder<R>),
+}
+
+impl<R: Read> Read for Receiver<R> {
+  fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+    match self {
+      Receiver::Stream(r) => r.read(out),
+      Receiver::Zlib(z) => z.read(out),
+    }
+  }
+}
+
+/// STRU
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Structure {
+  /// STRU F
+  #[default]
+  File,
+}
+
+impl Structure {
+  /// Parses the argument of `STRU`, the error is the reply code to answer with.
+  pub fn from_arg(arg: &str) -> Result<Structure, u64> {
+    match &*arg.trim().to_uppercase() {
+      "F" => Ok(Structure::File),
+      "" => Err(BADOPTS),
+      _ => Err(BADSTRU),
+    }
+  }
+}
+
+/// LF -> CRLF, used when sending in TYPE A.
+/// A bare LF gets a CR in front, existing CRLF pairs are left alone.
+pub struct AsciiReader<R> {
+  inner: R,
+  buf: Vec<u8>,
+  pos: usize,
+  last_cr: bool,
+}
+
+impl<R: Read> AsciiReader<R> {
+  pub fn new(inner: R) -> Self {
+    AsciiReader {
+      inner,
+      buf: Vec::new(),
+      pos: 0,
+      last_cr: false,
+    }
+  }
+
+  pub fn into_inner(self) -> R {
+    self.inner
+  }
+
+  fn fill(&mut self) -> io::Result<()> {
+    let mut raw = [0u8; 4096];
+    let n = self.inner.read(&mut raw)?;
+    self.buf.clear();
+    self.pos = 0;
+    for &b in &raw[..n] {
+      if b == b'\n' && !self.last_cr {
+        self.buf.push(b'\r');
+      }
+      self.last_cr = b == b'\r';
+      self.buf.push(b);
+    }
+    Ok(())
+  }
+}
+
+impl<R: Read> Read for AsciiReader<R> {
+  fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+    if self.pos == self.buf.len() {
+      self.fill()?;
+    }
+    let n = out.len().min(self.buf.len() - self.pos);
+    out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+    self.pos += n;
+    Ok(n)
+  }
+}
+
+/// CRLF -> LF, used when receiving in TYPE A.
+/// A CR is held back until the next byte shows whether it starts a CRLF pair,
+/// call `finish` so a trailing lone CR still reaches the file.
+pub struct AsciiWriter<W: Write> {
+  inner: W,
+  pending_cr: bool,
+}
+
+impl<W: Write> AsciiWriter<W> {
+  pub fn new(inner: W) -> Self {
+    AsciiWriter {
+      inner,
+      pending_cr: false,
+    }
+  }
+
+  pub fn finish(mut self) -> io::Result<W> {
+    if self.pending_cr {
+      self.inner.write_all(b"\r")?;
+    }
+    self.inner.flush()?;
+    Ok(self.inner)
+  }
+}
+
+impl<W: Write> Write for AsciiWriter<W> {
+  fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+    let mut out = Vec::with_capacity(data.len() + 1);
+    for &b in data {
+      if self.pending_cr && b != b'\n' {
+        out.push(b'\r');
+      }
+      self.pending_cr = b == b'\r';
+      if !self.pending_cr {
+        out.push(b);
+      }
+    }
+    self.inner.write_all(&out)?;
+    Ok(data.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
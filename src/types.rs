@@ -0,0 +1,283 @@
+use std::fmt::{self, Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::time;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+/// Unix umask, written in octal like `077`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Umask(u32);
+
+impl Umask {
+  pub const fn new(bits: u32) -> Option<Umask> {
+    if bits <= 0o777 {
+      Some(Umask(bits))
+    } else {
+      None
+    }
+  }
+
+  pub const fn bits(self) -> u32 {
+    self.0
+  }
+
+  /// Permission bits left after masking `mode`, e.g. 0o777 -> 0o700 with `077`
+  pub const fn apply(self, mode: u32) -> u32 {
+    mode & !self.0
+  }
+}
+
+impl FromStr for Umask {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Umask, String> {
+    u32::from_str_radix(s, 8)
+      .ok()
+      .filter(|_| !s.starts_with('+'))
+      .and_then(Umask::new)
+      .ok_or_else(|| format!("invalid umask `{}`, expected octal digits up to 777", s))
+  }
+}
+
+impl Display for Umask {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{:03o}", self.0)
+  }
+}
+
+/// Whole seconds, written as `30`, `30s`, `5m` or `1h`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Duration(u64);
+
+impl Duration {
+  pub const fn from_secs(secs: u64) -> Duration {
+    Duration(secs)
+  }
+
+  pub const fn as_secs(self) -> u64 {
+    self.0
+  }
+}
+
+impl From<Duration> for time::Duration {
+  fn from(d: Duration) -> Self {
+    time::Duration::from_secs(d.0)
+  }
+}
+
+impl FromStr for Duration {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Duration, String> {
+    let units = [('s', 1), ('m', 60), ('h', 60 * 60), ('d', 24 * 60 * 60)];
+    parse_with_unit(s, &units)
+      .map(Duration)
+      .ok_or_else(|| format!("invalid duration `{}`, expected e.g. 30, 30s, 5m or 1h", s))
+  }
+}
+
+impl Display for Duration {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let units = [('d', 24 * 60 * 60), ('h', 60 * 60), ('m', 60)];
+    fmt_with_unit(f, self.0, &units, "s")
+  }
+}
+
+/// Bytes per second, written as `102400`, `100K` or `2M`, 0 means unlimited
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct ByteRate(u64);
+
+impl ByteRate {
+  pub const fn from_bytes(bytes: u64) -> ByteRate {
+    ByteRate(bytes)
+  }
+
+  pub const fn as_bytes(self) -> u64 {
+    self.0
+  }
+
+  pub const fn is_unlimited(self) -> bool {
+    self.0 == 0
+  }
+}
+
+impl FromStr for ByteRate {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<ByteRate, String> {
+    let units = [('k', 1 << 10), ('m', 1 << 20), ('g', 1 << 30)];
+    parse_with_unit(&s.to_lowercase(), &units)
+      .map(ByteRate)
+      .ok_or_else(|| format!("invalid rate `{}`, expected bytes or e.g. 100K, 2M", s))
+  }
+}
+
+impl Display for ByteRate {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let units = [('G', 1 << 30), ('M', 1 << 20), ('K', 1 << 10)];
+    fmt_with_unit(f, self.0, &units, "")
+  }
+}
+
+/// An address block like `10.0.0.0/8`, a bare address is a block of one.
+/// Host bits are cleared, so `10.0.0.1/8` is the same block as `10.0.0.0/8`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct IpNet {
+  addr: IpAddr,
+  prefix_len: u8,
+}
+
+impl IpNet {
+  pub fn prefix_len(&self) -> u8 {
+    self.prefix_len
+  }
+
+  pub fn contains(&self, ip: IpAddr) -> bool {
+    match (self.addr, ip) {
+      (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+        network(ip, self.prefix_len) == self.addr
+      }
+      _ => false,
+    }
+  }
+}
+
+impl FromStr for IpNet {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<IpNet, String> {
+    let invalid = || format!("invalid address block `{}`, expected e.g. 10.0.0.0/8", s);
+    let mut parts = s.splitn(2, '/');
+    let addr: IpAddr = parts
+      .next()
+      .unwrap_or_default()
+      .parse()
+      .map_err(|_| invalid())?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix_len = match parts.next() {
+      Some(len) => len
+        .parse()
+        .ok()
+        .filter(|&len| len <= max)
+        .ok_or_else(invalid)?,
+      None => max,
+    };
+    Ok(IpNet {
+      addr: network(addr, prefix_len),
+      prefix_len,
+    })
+  }
+}
+
+/// `addr` with the bits after the first `prefix_len` cleared
+fn network(addr: IpAddr, prefix_len: u8) -> IpAddr {
+  match addr {
+    IpAddr::V4(addr) => {
+      let mask = u32::MAX
+        .checked_shl(32 - u32::from(prefix_len))
+        .unwrap_or(0);
+      IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask))
+    }
+    IpAddr::V6(addr) => {
+      let mask = u128::MAX
+        .checked_shl(128 - u32::from(prefix_len))
+        .unwrap_or(0);
+      IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask))
+    }
+  }
+}
+
+impl Display for IpNet {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}/{}", self.addr, self.prefix_len)
+  }
+}
+
+/// A `SITE` subcommand, written in lower case in `site_commands`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SiteCommand {
+  Chmod,
+  Umask,
+  Idle,
+  Help,
+}
+
+impl SiteCommand {
+  pub const ALL: [SiteCommand; 4] = [
+    SiteCommand::Chmod,
+    SiteCommand::Umask,
+    SiteCommand::Idle,
+    SiteCommand::Help,
+  ];
+
+  pub const fn name(self) -> &'static str {
+    match self {
+      SiteCommand::Chmod => "chmod",
+      SiteCommand::Umask => "umask",
+      SiteCommand::Idle => "idle",
+      SiteCommand::Help => "help",
+    }
+  }
+}
+
+impl FromStr for SiteCommand {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<SiteCommand, String> {
+    SiteCommand::ALL
+      .iter()
+      .copied()
+      .find(|command| command.name().eq_ignore_ascii_case(s))
+      .ok_or_else(|| format!("unknown SITE command `{}`, expected chmod, umask, idle or help", s))
+  }
+}
+
+impl Display for SiteCommand {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
+  }
+}
+
+/// `n` or `n<unit>`, multiplied out
+fn parse_with_unit(s: &str, units: &[(char, u64)]) -> Option<u64> {
+  let (digits, scale) = match units.iter().find(|&&(unit, _)| s.ends_with(unit)) {
+    Some(&(unit, scale)) => (&s[..s.len() - unit.len_utf8()], scale),
+    None => (s, 1),
+  };
+  if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+    return None;
+  }
+  digits.parse::<u64>().ok()?.checked_mul(scale)
+}
+
+/// The largest unit that divides `n` exactly
+fn fmt_with_unit(f: &mut Formatter<'_>, n: u64, units: &[(char, u64)], base: &str) -> fmt::Result {
+  match units
+    .iter()
+    .find(|&&(_, scale)| n != 0 && n.is_multiple_of(scale))
+  {
+    Some(&(unit, scale)) => write!(f, "{}{}", n / scale, unit),
+    None => write!(f, "{}{}", n, base),
+  }
+}
+
+/// Reads each type from its text with `FromStr` and writes it with `Display`
+macro_rules! with_text_repr {
+  ($($ty:ident)*) => {$(
+    impl<'de> Deserialize<'de> for $ty {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+      }
+    }
+
+    impl Serialize for $ty {
+      fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+      }
+    }
+  )*};
+}
+
+with_text_repr! { Umask Duration ByteRate IpNet SiteCommand }
@@ -0,0 +1,72 @@
+use std::fs::File;
+
+use super::conf::{self, Document};
+use super::config::Config;
+use super::err::FtpdError;
+use super::types::{ByteRate, Duration};
+
+/// vsftpd directives spelled differently here, and the keys they set
+const RENAMED: &[(&str, &[&str])] =
+  &[("local_max_rate", &["upload_max_rate", "download_max_rate"])];
+
+/// Keys where vsftpd reads 0 as no limit
+const UNLIMITED_IF_ZERO: &[&str] = &["max_clients", "max_per_ip"];
+
+/// What vsftpd does for the directives a vsftpd.conf leaves out, where that
+/// differs from an empty ftpd.conf
+fn defaults() -> Config {
+  Config {
+    max_clients: u32::MAX,
+    max_per_ip: u32::MAX,
+    connect_timeout: Duration::from_secs(60),
+    data_connection_timeout: Duration::from_secs(300),
+    upload_max_rate: ByteRate::from_bytes(0),
+    download_max_rate: ByteRate::from_bytes(0),
+    ..Config::default()
+  }
+}
+
+/// Loads a vsftpd.conf. Directives with a matching key are applied, the rest
+/// come back as warnings. Values are taken as written, `${VAR}` is not expanded.
+pub fn load(path: &str) -> Result<(Config, Vec<String>), FtpdError> {
+  let file =
+    File::open(path).map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+  from_document(&Document::from_read(file)?)
+}
+
+/// `load` over the text of a vsftpd.conf
+pub fn from_str(text: &str) -> Result<(Config, Vec<String>), FtpdError> {
+  from_document(&text.parse()?)
+}
+
+fn from_document(doc: &Document) -> Result<(Config, Vec<String>), FtpdError> {
+  let mut config = defaults();
+  let mut warnings = Vec::new();
+  for (section, key, value) in doc.entries() {
+    if let Some(section) = section {
+      warnings.push(format!("`{}` in `[{}]` ignored, vsftpd.conf has no sections", key, section));
+      continue;
+    }
+    let keys = match RENAMED.iter().find(|(name, _)| *name == key) {
+      Some(&(_, keys)) => keys,
+      None if Config::KEYS.contains(&key) => std::slice::from_ref(&key),
+      None => {
+        warnings.push(format!("`{}` is not supported and was ignored", key));
+        continue;
+      }
+    };
+    for &key in keys {
+      let value = match value {
+        "0" if UNLIMITED_IF_ZERO.contains(&key) => u32::MAX.to_string(),
+        value => value.to_string(),
+      };
+      config
+        .set(key, &value)
+        .map_err(|e| conf::Error::Custom(format!("{}: {}", key, e)))?;
+    }
+  }
+  // vsftpd lets max_per_ip run above max_clients, which caps it anyway
+  config.max_per_ip = config.max_per_ip.min(config.max_clients);
+  config.validate()?;
+  Ok((config, warnings))
+}
@@ -1,8 +1,943 @@
 mod test {
-  use ftpd::config::Config;
+  use ftpd::builder::{ConfigBuilder, Source};
+  use ftpd::conf;
+  use ftpd::config::{Config, Overrides};
+  use ftpd::reload::SharedConfig;
+  use ftpd::status;
+  use ftpd::transfer::{AsciiReader, AsciiWriter, Mode, TransferType};
+  use ftpd::types::Duration;
+  use ftpd::FtpdError;
+  use std::fs;
+  use std::io::{Read, Write};
+  use std::net::IpAddr;
+
   #[test]
   fn load_file() {
     let a = Config::new("examples/ftpd.conf").unwrap();
     assert!(a.listen_address.is_some());
+    assert!(matches!(Config::new("examples/missing.conf"), Err(FtpdError::Io(_))));
+    let b: Config = "listen_port = 2121".parse().unwrap();
+    assert_eq!(b.listen_port, 2121);
+  }
+
+  #[test]
+  fn ascii_line_endings() {
+    assert_eq!(TransferType::from_arg("L 8"), Ok(TransferType::Image));
+    assert_eq!(TransferType::from_arg("E"), Err(status::BADMODE));
+    assert_eq!(TransferType::from_arg("L 7"), Err(status::BADMODE));
+    assert_eq!(TransferType::from_arg("A T"), Err(status::BADMODE));
+    assert_eq!(TransferType::from_arg(""), Err(status::BADOPTS));
+    let mut sent = Vec::new();
+    AsciiReader::new(&b"a\nb\r\nc\n"[..])
+      .read_to_end(&mut sent)
+      .unwrap();
+    assert_eq!(sent, b"a\r\nb\r\nc\r\n");
+
+    let mut w = AsciiWriter::new(Vec::new());
+    w.write_all(b"a\r").unwrap();
+    w.write_all(b"\nb\rc\r").unwrap();
+    assert_eq!(w.finish().unwrap(), b"a\nb\rc\r");
+  }
+
+  #[test]
+  fn abort() {
+    use ftpd::abort::{self, AbortHandle};
+    assert_eq!(abort::strip_telnet(b"\xff\xf4\xff\xf2ABOR"), b"ABOR");
+    assert_eq!(abort::strip_telnet(b"\xff\xf4\xffABOR"), b"ABOR");
+    assert_eq!(abort::strip_telnet(b"\xff\xfb\x01RETR a\xff\xffb"), b"RETR a\xffb");
+    assert_eq!(abort::replies(true), [status::BADSENDNET, status::ABOROK]);
+    assert_eq!(abort::replies(false), [status::ABORNOCONN]);
+
+    let handle = AbortHandle::new();
+    let mut data = handle.wrap(&b"0123456789"[..]);
+    let mut buf = [0; 4];
+    assert_eq!(data.read(&mut buf).unwrap(), 4);
+    let control = handle.clone();
+    std::thread::spawn(move || control.abort()).join().unwrap();
+    let e = data.read(&mut buf).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::ConnectionAborted);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (socket, _) = listener.accept().unwrap();
+    let handle = AbortHandle::new();
+    let mut data = handle.wrap_socket(socket).unwrap();
+    let transfer = std::thread::spawn(move || data.read(&mut [0; 16]));
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    handle.abort();
+    let e = transfer.join().unwrap().unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::ConnectionAborted);
+    drop(client);
+  }
+
+  #[test]
+  fn site() {
+    use ftpd::site::{self, Site};
+    let c: Config =
+      concat!("idle_session_timeout = 10m\n", "[user:guest]\n", "site_commands = help, idle\n",)
+        .parse()
+        .unwrap();
+    let ip = "10.0.0.1".parse().unwrap();
+    let bob = c.for_session(Some("bob"), ip);
+    let chmod = Site::from_arg("CHMOD 644 my file.txt", &bob).unwrap();
+    assert_eq!(
+      chmod,
+      Site::Chmod {
+        mode: 0o644,
+        path: "my file.txt".into()
+      }
+    );
+    assert_eq!(chmod.code(), status::CHMODOK);
+    assert_eq!(Site::from_arg("chmod 1777 a", &bob), Err(status::BADOPTS));
+    assert_eq!(Site::from_arg("CHMOD 644", &bob), Err(status::BADOPTS));
+    assert_eq!(Site::from_arg("EXEC ls", &bob), Err(status::BADCMD));
+
+    let root = std::env::temp_dir().join(format!("ftpd-site-{}", std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("my file.txt"), "").unwrap();
+    chmod.chmod(&root).unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = fs::metadata(root.join("my file.txt"))
+        .unwrap()
+        .permissions()
+        .mode();
+      assert_eq!(mode & 0o777, 0o644);
+      let chmod = Site::from_arg("CHMOD 600 /../my file.txt", &bob).unwrap();
+      chmod.chmod(&root).unwrap();
+      let mode = fs::metadata(root.join("my file.txt"))
+        .unwrap()
+        .permissions()
+        .mode();
+      assert_eq!(mode & 0o777, 0o600);
+    }
+    let missing = Site::from_arg("CHMOD 644 missing.txt", &bob).unwrap();
+    assert_eq!(missing.chmod(&root), Err(status::FILEFAIL));
+    fs::remove_dir_all(root).unwrap();
+
+    let mut session = bob.clone();
+    let umask = Site::from_arg("UMASK 022", &bob).unwrap();
+    umask.apply(&mut session);
+    assert_eq!(session.local_umask.bits(), 0o022);
+    assert_eq!(umask.message(&session), "UMASK set to 022");
+    assert_eq!(Site::from_arg("UMASK 0999", &bob), Err(status::BADOPTS));
+
+    let idle = Site::from_arg("IDLE 600", &bob).unwrap();
+    idle.apply(&mut session);
+    assert_eq!(session.idle_session_timeout.as_secs(), 600);
+    assert_eq!(Site::from_arg("IDLE 601", &bob), Err(status::BADOPTS));
+    assert_eq!(Site::from_arg("IDLE", &bob), Ok(Site::Idle(None)));
+
+    let guest = c.for_session(Some("guest"), ip);
+    assert_eq!(Site::from_arg("UMASK 022", &guest), Err(status::NOPERM));
+    assert_eq!(site::help(&guest), "IDLE HELP");
+    assert_eq!(Site::Help.message(&bob), "CHMOD UMASK IDLE HELP");
+    assert_eq!(Site::Help.code(), status::SITEHELP);
+  }
+
+  #[test]
+  fn stat() {
+    let c: Config = "[ip:10.0.0.0/8]\nupload_max_rate = 0\nidle_session_timeout = 2m\n"
+      .parse()
+      .unwrap();
+    let peer = "10.1.2.3".parse().unwrap();
+    let reply =
+      ftpd::stat::server_status(&c, Some("bob"), peer, TransferType::Image, Mode::Zlib(6));
+    assert!(reply.starts_with("211-FTP server status:\r\n     Connected to 10.1.2.3\r\n"));
+    assert!(reply.contains("     Logged in as bob\r\n     TYPE: BINARY, MODE: Z, zlib level 6\r\n"));
+    assert!(reply.contains("     No upload rate limit\r\n"));
+    assert!(reply.contains("     Download rate limit is 200K bytes per second\r\n"));
+    assert!(reply.contains("     Session timeout in seconds is 120\r\n"));
+    assert!(reply.ends_with("\r\n211 End of status\r\n"));
+    assert_eq!(status::reply(status::STATOK, &["ok"]), "211 ok\r\n");
+
+    let root = std::env::temp_dir().join(format!("ftpd-stat-{}", std::process::id()));
+    fs::create_dir_all(root.join("pub/sub")).unwrap();
+    let file = fs::File::create(root.join("pub/a.txt")).unwrap();
+    file.set_len(5).unwrap();
+    let march_2023 = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1678026600);
+    file.set_modified(march_2023).unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      fs::set_permissions(root.join("pub/a.txt"), fs::Permissions::from_mode(0o644)).unwrap();
+      fs::set_permissions(root.join("pub/sub"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let reply = ftpd::stat::file_status(&root, "/pub").unwrap();
+    let lines: Vec<_> = reply.split("\r\n").collect();
+    assert_eq!(lines[0], "213-Status of /pub:");
+    assert_eq!(lines[1], "-rw-r--r-- 1 ftp ftp        5 Mar 05  2023 a.txt");
+    assert!(lines[2].starts_with("drwxr-xr-x 1 ftp ftp ") && lines[2].ends_with(" sub"));
+    assert_eq!(&lines[3..], ["213 End of status", ""]);
+    let reply = ftpd::stat::file_status(&root, "pub/../../pub/a.txt").unwrap();
+    assert!(reply.contains("\r\n-rw-r--r-- 1 ftp ftp        5 Mar 05  2023 a.txt\r\n"));
+    assert_eq!(ftpd::stat::file_status(&root, "missing"), Err(status::FILEFAIL));
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn zlib_mode() {
+    let level = Mode::zlib_level_from_opts("MODE Z LEVEL 9").unwrap();
+    let mut tx = Mode::Zlib(level).sender(Vec::new());
+    tx.write_all(&b"log line\n"[..].repeat(100)).unwrap();
+    let wire = tx.finish().unwrap();
+    assert!(wire.len() < 100);
+
+    let mut rx = Vec::new();
+    Mode::from_arg("z", level)
+      .unwrap()
+      .receiver(&wire[..])
+      .read_to_end(&mut rx)
+      .unwrap();
+    assert_eq!(rx, b"log line\n".repeat(100));
+
+    assert_eq!(Mode::from_arg("Z", level), Ok(Mode::Zlib(9)));
+    assert_eq!(Mode::from_arg("Z", ftpd::transfer::ZLIB_LEVEL_DEFAULT), Ok(Mode::Zlib(6)));
+    assert_eq!(Mode::from_arg("S", level), Ok(Mode::Stream));
+  }
+
+  #[test]
+  fn error_location() {
+    let e = conf::from_str::<Config>("pasv_enable = yes\n\nlisten_port = 21x\n").unwrap_err();
+    assert_eq!(e.key(), Some("listen_port"));
+    assert_eq!(e.position().map(|p| (p.line, p.column)), Some((3, 15)));
+    assert_eq!(
+      e.with_file("ftpd.conf").to_string(),
+      "ftpd.conf:3:15: invalid digit found in string (key `listen_port`)\n  \
+       |\n3 | listen_port = 21x\n  |               ^^^"
+    );
+
+    let e = conf::from_str::<Config>("max_clients = 3\nmax_per_ip\n").unwrap_err();
+    assert_eq!(e.position().map(|p| p.line), Some(2));
+
+    use serde_derive::Deserialize;
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Limits {
+      a: u32,
+      b: u32,
+    }
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Server {
+      port: u32,
+      limits: Limits,
+    }
+    let e = conf::from_str::<Limits>("a = 1\n").unwrap_err();
+    assert_eq!(e.to_string(), "missing field `b` at the top level");
+    assert_eq!(
+      e.with_file("ftpd.conf").to_string(),
+      "ftpd.conf: missing field `b` at the top level"
+    );
+    let e = conf::from_str::<Server>("port = 21\n[limits]\na = 1\n").unwrap_err();
+    assert_eq!(e.position().map(|p| p.line), Some(2));
+    assert!(
+      e.to_string()
+        .starts_with("line 2, column 1: missing field `b` in `[limits]`"),
+      "{}",
+      e
+    );
+    let e = conf::from_str::<Server>("limits.b = 1\nport = 21\n").unwrap_err();
+    assert_eq!(e.position().map(|p| p.line), Some(1));
+    assert!(e.to_string().contains("missing field `a` in `limits`"), "{}", e);
+  }
+
+  #[test]
+  fn strict_keys() {
+    let e = "max_perip = 5".parse::<Config>().unwrap_err().to_string();
+    assert!(e.starts_with("line 1, column 1: unknown key `max_perip`, did you mean `max_per_ip`?"));
+    let e = "listen_port = 1\nlisten_port = 2"
+      .parse::<Config>()
+      .unwrap_err();
+    assert!(e
+      .to_string()
+      .contains("duplicate key `listen_port`, first set on line 1"));
+    assert!(conf::from_str::<Config>("max_perip = 5").is_ok());
+  }
+
+  #[test]
+  fn bool_values() {
+    let c: Config = conf::from_str("pasv_enable = NO\nport_enable = off").unwrap();
+    assert!(!c.pasv_enable && !c.port_enable);
+    let e = "pasv_enable = ture"
+      .parse::<Config>()
+      .unwrap_err()
+      .to_string();
+    assert!(e.contains("invalid boolean `ture`, expected one of true, false, yes, no"));
+  }
+
+  #[test]
+  fn validate() {
+    let e = concat!(
+      "port_enable = no\n",
+      "listen_port = 70000\n",
+      "listen_address = localhost\n",
+      "pasv_min_port = 5000\n",
+      "pasv_max_port = 4000\n",
+      "[user:bob]\n",
+      "pasv_enable = no\n",
+    )
+    .parse::<Config>()
+    .unwrap_err();
+    match e {
+      FtpdError::Validation(problems) => assert_eq!(
+        problems,
+        [
+          "listen_port 70000 is above 65535",
+          "listen_address `localhost` is not an IP address",
+          "pasv_min_port 5000 is above pasv_max_port 4000",
+          "[user:bob] pasv_enable and port_enable are both off, no transfer can start",
+        ]
+      ),
+      e => panic!("{}", e),
+    }
+    let c: Config = conf::from_str("max_per_ip = 4").unwrap();
+    assert!(c.validate().is_err());
+
+    let c: Config = conf::from_str(concat!(
+      "pasv_enable = no\n",
+      "port_enable = no\n",
+      "[user:bob]\n",
+      "max_per_ip = 5\n",
+      "[ip:10.0.0.0/8]\n",
+      "pasv_enable = yes\n",
+    ))
+    .unwrap();
+    match c.validate().unwrap_err() {
+      FtpdError::Validation(problems) => assert_eq!(
+        problems,
+        [
+          "pasv_enable and port_enable are both off, no transfer can start",
+          "[user:bob] max_per_ip 5 is above max_clients 3",
+        ]
+      ),
+      e => panic!("{}", e),
+    }
+  }
+
+  #[test]
+  fn typed_values() {
+    let c: Config = "local_umask = 022\nidle_session_timeout = 5m\ndownload_max_rate = 2M\n\
+                     accept_timeout = 45"
+      .parse()
+      .unwrap();
+    assert_eq!(c.local_umask.apply(0o777), 0o755);
+    assert_eq!(c.idle_session_timeout.as_secs(), 300);
+    assert_eq!(c.accept_timeout.to_string(), "45s");
+    assert_eq!(c.download_max_rate.as_bytes(), 2 * 1024 * 1024);
+    assert_eq!(c.upload_max_rate.to_string(), "100K");
+
+    let e = "local_umask = 999"
+      .parse::<Config>()
+      .unwrap_err()
+      .to_string();
+    assert!(e.contains("invalid umask `999`"));
+
+    let net: ftpd::types::IpNet = "10.1.2.3/8".parse().unwrap();
+    assert_eq!(net, "10.0.0.0/8".parse().unwrap());
+    assert_eq!(net.to_string(), "10.0.0.0/8");
+    assert!(
+      net.contains("10.255.0.1".parse().unwrap()) && !net.contains("11.0.0.1".parse().unwrap())
+    );
+    let net: ftpd::types::IpNet = "fd00::1/64".parse().unwrap();
+    assert_eq!(net.to_string(), "fd00::/64");
+    let c = conf::from_str::<Config>("[ip:10.0.0.1/8]\n[ip:10.0.0.0/8]\n").unwrap();
+    assert_eq!(c.ip.len(), 1);
+  }
+
+  #[test]
+  fn sections() {
+    let c: Config = "download_max_rate = 100K\n\
+                     [ip:10.0.0.0/8]\ndownload_max_rate = 1M\nidle_session_timeout = 1h\n\
+                     [ip:10.1.0.0/16]\ndownload_max_rate = 2M\n\
+                     [user:alice]\ndownload_max_rate = 0\n"
+      .parse()
+      .unwrap();
+    let ip: IpAddr = "10.1.2.3".parse().unwrap();
+    let s = c.for_session(Some("bob"), ip);
+    assert_eq!(s.download_max_rate.to_string(), "2M");
+    assert_eq!(s.idle_session_timeout.to_string(), "1h");
+    assert!(c
+      .for_session(Some("alice"), ip)
+      .download_max_rate
+      .is_unlimited());
+    let other = c.for_session(None, "192.168.0.1".parse().unwrap());
+    assert_eq!(other.download_max_rate.to_string(), "100K");
+
+    let c = "[user:a]\n[ip:::1]\nmax_per_ip = 1\n[user:b]\nmax_per_ip = 2\n"
+      .parse::<Config>()
+      .unwrap();
+    assert_eq!((c.user.len(), c.user["b"].max_per_ip, c.ip.len()), (2, Some(2), 1));
+    let e = "[user:a]\n[ip:::1]\n[user: a ]"
+      .parse::<Config>()
+      .unwrap_err()
+      .to_string();
+    assert!(
+      e.starts_with("line 3, column 1: duplicate section `[user:a]`, the first one is on line 1"),
+      "{}",
+      e
+    );
+    let c = conf::from_str::<Config>(
+      "[user:a]\nmax_per_ip = 1\n\
+       [ip:10.0.0.0/8]\nmax_per_ip = 2\n\
+       # b\n[user:b]\nmax_per_ip = 3\n\
+       [ip:::1]\nmax_per_ip = 4\n",
+    )
+    .unwrap();
+    assert_eq!(c.user["a"].max_per_ip, Some(1));
+    assert_eq!(c.user["b"].max_per_ip, Some(3));
+    assert_eq!(c.ip.len(), 2);
+    assert_eq!(c.for_session(None, "::1".parse().unwrap()).max_per_ip, 4);
+    assert_eq!(c.for_session(None, ip).max_per_ip, 2);
+    let e = "[user:a]\nlisten_port = 21"
+      .parse::<Config>()
+      .unwrap_err()
+      .to_string();
+    assert!(e.starts_with("line 2, column 1: unknown key `listen_port`"));
+  }
+
+  #[test]
+  fn includes() {
+    let dir = std::env::temp_dir().join(format!("ftpd-include-{}", std::process::id()));
+    fs::create_dir_all(dir.join("conf.d")).unwrap();
+    let main = dir.join("ftpd.conf");
+    fs::write(&main, "listen_port = 2121\ninclude = conf.d/*.conf\n").unwrap();
+    fs::write(dir.join("conf.d/b.conf"), "max_clients = 10\n").unwrap();
+    fs::write(dir.join("conf.d/a.conf"), "max_per_ip = 5\n").unwrap();
+    let c = Config::new(main.to_str().unwrap()).unwrap();
+    assert_eq!((c.listen_port, c.max_per_ip, c.max_clients), (2121, 5, 10));
+
+    fs::write(dir.join("conf.d/b.conf"), "max_clients = ten\n").unwrap();
+    let e = Config::new(main.to_str().unwrap()).unwrap_err().to_string();
+    assert!(e.contains("b.conf:1:15: invalid digit found in string"), "{}", e);
+
+    fs::write(dir.join("conf.d/b.conf"), "include = ../ftpd.conf\n").unwrap();
+    let e = Config::new(main.to_str().unwrap()).unwrap_err().to_string();
+    assert!(e.contains("b.conf:1:11: syntax error file includes itself"), "{}", e);
+
+    fs::write(dir.join("conf.d/b.conf"), "include = missing.conf\n").unwrap();
+    let e = Config::new(main.to_str().unwrap()).unwrap_err().to_string();
+    assert!(e.contains("b.conf:1:11: "), "{}", e);
+    assert!(e.contains("missing.conf: No such file"), "{}", e);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn environment() {
+    std::env::set_var("FTPD_TEST_PORT", "2121");
+    let c: Config = "listen_port = ${FTPD_TEST_PORT}\nlisten_address = ${FTPD_TEST_ADDR:-0.0.0.0}"
+      .parse()
+      .unwrap();
+    assert_eq!(c.listen_port, 2121);
+    assert_eq!(c.listen_address.as_deref(), Some("0.0.0.0"));
+    let e = "listen_address = 10.0.0.1:${FTPD_TEST_UNSET}"
+      .parse::<Config>()
+      .unwrap_err();
+    assert_eq!(
+      e.to_string().lines().last(),
+      Some("  |                           ^^^^^^^^^^^^^^^^^^")
+    );
+
+    let mut c = c;
+    let vars = vec![
+      ("FTPD_PASV_ENABLE".into(), "NO".into()),
+      ("FTPD_CONF".into(), "x".into()),
+    ];
+    c.apply_vars(vars).unwrap();
+    assert!(!c.pasv_enable);
+    let e = c
+      .apply_vars(vec![("FTPD_MAX_CLIENTS".into(), "x".into())])
+      .unwrap_err();
+    assert_eq!(e.to_string(), "FTPD_MAX_CLIENTS: invalid digit found in string");
+  }
+
+  #[test]
+  fn lists() {
+    #[derive(Debug, serde_derive::Deserialize)]
+    struct Lists {
+      deny: Vec<String>,
+      ports: Vec<u16>,
+      empty: Vec<u16>,
+    }
+    let l: Lists =
+      conf::from_str("deny = root, \"a, b\" ,bin\ndeny = \" x\\\"y\"\nports = 20,21\nempty =\n")
+        .unwrap();
+    assert_eq!(l.deny, ["root", "a, b", "bin", " x\"y"]);
+    assert_eq!(l.ports, [20, 21]);
+    assert!(l.empty.is_empty());
+
+    let e = conf::from_str::<Lists>("ports = 20\nports = 21, x\n").unwrap_err();
+    assert_eq!(e.position().map(|p| p.line), Some(2));
+  }
+
+  #[test]
+  fn quoting() {
+    #[derive(Debug, serde_derive::Deserialize)]
+    struct Banner {
+      banner: String,
+      motd: String,
+      deny: Vec<String>,
+      port: u16,
+      note: String,
+    }
+    let b: Banner = conf::from_str(concat!(
+      "banner = \"  Welcome; \\\"FTP\\\" # \\${HOME}\\n\" # greeting\n",
+      "motd = first \\\n",
+      "   second\n",
+      "deny = \"a,b\" ; one element\n",
+      "port = 2121 # dev\n",
+      "note = a#b\n",
+    ))
+    .unwrap();
+    assert_eq!(b.banner, "  Welcome; \"FTP\" # ${HOME}\n");
+    assert_eq!(b.motd, "first second");
+    assert_eq!(b.deny, ["a,b"]);
+    assert_eq!((b.port, &*b.note), (2121, "a#b"));
+
+    let e = conf::from_str::<Banner>("banner = \"open")
+      .unwrap_err()
+      .to_string();
+    assert!(e.starts_with("line 1, column 10: syntax error missing closing '\"'"), "{}", e);
+
+    let text = "dir = \"C:\\\\ftp\\\\\"\n  # indented ; comment\n  # a = b\nport = 21\n";
+    let m: std::collections::HashMap<String, String> = conf::from_str(text).unwrap();
+    assert_eq!(m.len(), 2);
+    assert_eq!((&*m["dir"], &*m["port"]), ("C:\\ftp\\", "21"));
+    let doc: conf::Document = text.parse().unwrap();
+    assert_eq!(doc.to_string(), text);
+    assert_eq!(doc.get(None, "dir"), Some("C:\\ftp\\"));
+    let m: std::collections::HashMap<String, String> =
+      conf::from_str("motd = a \\\nb\nport = 21\n").unwrap();
+    assert_eq!((&*m["motd"], &*m["port"]), ("a b", "21"));
+    let e = conf::from_str::<Banner>("note = \"x\" y\n")
+      .unwrap_err()
+      .to_string();
+    assert!(
+      e.starts_with("line 1, column 12: syntax error unexpected text after closing '\"'"),
+      "{}",
+      e
+    );
+    let e = conf::from_str::<Banner>("note = C:\\ftp\\\n")
+      .unwrap_err()
+      .to_string();
+    assert!(e.starts_with("line 1, column 14: syntax error '\\' continues past the end"), "{}", e);
+    assert_eq!(doc.get(None, "port"), Some("21"));
+  }
+
+  #[test]
+  fn serialize() {
+    let c: Config = concat!(
+      "listen_port = 2121\n",
+      "max_clients = 10\n",
+      "idle_session_timeout = 10m\n",
+      "[user:bob]\n",
+      "pasv_enable = no\n",
+      "[ip:10.0.0.0/8]\n",
+      "max_per_ip = 5\n",
+    )
+    .parse()
+    .unwrap();
+    let text = conf::to_string(&c).unwrap();
+    assert!(text.starts_with("pasv_enable = true\nport_enable = true\nlocal_umask = 077\n"));
+    assert!(text.contains("idle_session_timeout = 10m\n"));
+    assert!(text.contains("\n[user:bob]\npasv_enable = false\n"));
+    let back: Config = text.parse().unwrap();
+    assert_eq!(back.listen_port, 2121);
+    assert_eq!(back.ip.values().next().unwrap().max_per_ip, Some(5));
+
+    let mut reference = Vec::new();
+    c.write_reference(&mut reference).unwrap();
+    let reference = String::from_utf8(reference).unwrap();
+    assert!(reference.contains("# Control connection port\n# default: 21\nlisten_port = 2121\n"));
+    assert!(reference.contains("#listen_address =\n"));
+    assert_eq!(reference.parse::<Config>().unwrap().listen_port, 2121);
+
+    #[derive(serde_derive::Serialize)]
+    struct Banner {
+      banner: &'static str,
+      deny: Vec<&'static str>,
+    }
+    let b = Banner {
+      banner: " hi ${USER}; \"x\"",
+      deny: vec!["root", "a, b"],
+    };
+    let text = conf::to_string(&b).unwrap();
+    assert_eq!(text, "banner = \" hi \\${USER}; \\\"x\\\"\"\ndeny = root, \"a, b\"\n");
+  }
+
+  #[test]
+  fn document() {
+    let text = concat!(
+      "# ftpd.conf\n",
+      "listen_port = 21   # the usual\n",
+      "home = ${HOME}\n",
+      "deny = root\n",
+      "deny = bin\n",
+      "\n",
+      "; per user\n",
+      "[user:bob]\n",
+      "  pasv_enable = no\n",
+      "motd = one \\\n",
+      "  two\n",
+    );
+    let mut doc: conf::Document = text.parse().unwrap();
+    assert_eq!(doc.to_string(), text);
+    assert_eq!(doc.get(None, "home"), Some("${HOME}"));
+    assert_eq!(doc.get(Some("user:bob"), "motd"), Some("one two"));
+    assert_eq!(doc.sections().collect::<Vec<_>>(), ["user:bob"]);
+
+    doc.set(None, "listen_port", "2121");
+    doc.set(None, "deny", "a, b");
+    doc.set(None, "max_clients", "10");
+    doc.set(Some("user:bob"), "pasv_enable", "yes");
+    doc.set(Some("ip:10.0.0.0/8"), "max_per_ip", "5");
+    assert!(doc.remove(Some("user:bob"), "motd"));
+    assert!(!doc.remove(None, "motd"));
+    assert_eq!(
+      doc.to_string(),
+      concat!(
+        "# ftpd.conf\n",
+        "listen_port = 2121   # the usual\n",
+        "home = ${HOME}\n",
+        "deny = a, b\n",
+        "max_clients = 10\n",
+        "\n",
+        "; per user\n",
+        "[user:bob]\n",
+        "  pasv_enable = yes\n",
+        "\n",
+        "[ip:10.0.0.0/8]\n",
+        "max_per_ip = 5\n",
+      )
+    );
+
+    doc.set(Some("user: alice "), "max_per_ip", "1");
+    doc.set(Some("user: alice "), "max_clients", "2");
+    assert_eq!(doc.get(Some("user: alice"), "max_per_ip"), Some("1"));
+    assert!(doc
+      .to_string()
+      .ends_with("\n[user: alice]\nmax_per_ip = 1\nmax_clients = 2\n"));
+    assert!(doc.remove(Some(" user: alice"), "max_clients"));
+    let reparsed: conf::Document = doc.to_string().parse().unwrap();
+    assert_eq!(reparsed.get(Some("user: alice"), "max_per_ip"), Some("1"));
+  }
+
+  #[test]
+  #[cfg(unix)]
+  fn reload() {
+    let dir = std::env::temp_dir().join(format!("ftpd-reload-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("ftpd.conf");
+    fs::write(&path, "download_max_rate = 1M\n").unwrap();
+    let shared = std::sync::Arc::new(SharedConfig::load(path.to_str().unwrap()).unwrap());
+    let started = shared.get();
+    let mut session = started.for_session(None, "127.0.0.1".parse().unwrap());
+
+    session.idle_session_timeout = Duration::from_secs(600);
+
+    fs::write(&path, "download_max_rate = 2M\nlisten_port = 2121\n").unwrap();
+    shared.reload_on_sighup().unwrap();
+    signal_hook::low_level::raise(signal_hook::consts::SIGHUP).unwrap();
+    for _ in 0..100 {
+      if shared.get().listen_port == 2121 {
+        break;
+      }
+      std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert_eq!(shared.get().listen_port, 2121);
+    assert_eq!(started.listen_port, 21);
+    shared.refresh(&mut session, None, "127.0.0.1".parse().unwrap());
+    assert_eq!((session.listen_port, session.download_max_rate.to_string().as_str()), (21, "2M"));
+    assert_eq!(session.idle_session_timeout.as_secs(), 600);
+
+    fs::write(&path, "pasv_enable = no\nport_enable = no\n").unwrap();
+    assert!(matches!(shared.reload(), Err(FtpdError::Validation(_))));
+    assert_eq!(shared.get().listen_port, 2121);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn vsftpd() {
+    let (c, warnings) = ftpd::vsftpd::from_str(concat!(
+      "# Example config file /etc/vsftpd.conf\n",
+      "listen=YES\n",
+      "anonymous_enable=NO\n",
+      "local_umask=022\n",
+      "pasv_enable=YES\n",
+      "port_enable=NO\n",
+      "pasv_min_port=40000\n",
+      "pasv_max_port=40100\n",
+      "max_clients=0\n",
+      "max_per_ip=5\n",
+      "local_max_rate=51200\n",
+      "ftpd_banner=Welcome to ${HOST}\n",
+    ))
+    .unwrap();
+    assert_eq!(c.local_umask.bits(), 0o022);
+    assert!(c.pasv_enable && !c.port_enable);
+    assert_eq!((c.pasv_min_port, c.pasv_max_port, c.max_per_ip), (40000, 40100, 5));
+    assert_eq!(c.max_clients, u32::MAX);
+    assert_eq!(c.upload_max_rate.as_bytes(), 51200);
+    assert_eq!(c.download_max_rate.as_bytes(), 51200);
+    assert_eq!(
+      warnings,
+      [
+        "`listen` is not supported and was ignored",
+        "`anonymous_enable` is not supported and was ignored",
+        "`ftpd_banner` is not supported and was ignored",
+      ]
+    );
+    let e = ftpd::vsftpd::from_str("max_per_ip=lots\n").unwrap_err();
+    assert!(e.to_string().starts_with("max_per_ip: "), "{}", e);
+
+    let (c, _) = ftpd::vsftpd::from_str("").unwrap();
+    assert_eq!((c.max_clients, c.max_per_ip), (u32::MAX, u32::MAX));
+    assert!(c.upload_max_rate.is_unlimited() && c.download_max_rate.is_unlimited());
+    assert_eq!(c.connect_timeout.as_secs(), 60);
+    assert_eq!(c.data_connection_timeout.as_secs(), 300);
+    assert_eq!(c.idle_session_timeout.as_secs(), 300);
+    let (c, _) = ftpd::vsftpd::from_str("max_per_ip=5\n").unwrap();
+    assert_eq!((c.max_clients, c.max_per_ip), (u32::MAX, 5));
+    let (c, _) = ftpd::vsftpd::from_str("max_per_ip=0\n").unwrap();
+    assert_eq!(c.max_per_ip, u32::MAX);
+    let (c, _) = ftpd::vsftpd::from_str("max_clients=3\n").unwrap();
+    assert_eq!((c.max_clients, c.max_per_ip), (3, 3));
+  }
+
+  #[test]
+  fn schema() {
+    let schema = ftpd::schema::json_schema();
+    let properties = &schema["properties"];
+    for key in Config::KEYS {
+      let p = &properties[key];
+      assert!(p.get("type").or_else(|| p.get("anyOf")).is_some(), "{}", key);
+      assert!(p["description"].is_string(), "{}", key);
+    }
+    let fields = serde_json::to_value(Config::default()).unwrap();
+    let fields: Vec<_> = fields.as_object().unwrap().keys().cloned().collect();
+    let mut keys: Vec<_> = Config::KEYS
+      .iter()
+      .chain(&["user", "ip"])
+      .map(|k| k.to_string())
+      .collect();
+    keys.sort();
+    assert_eq!(fields, keys);
+    let fields = serde_json::to_value(Overrides::default()).unwrap();
+    let mut fields: Vec<_> = fields.as_object().unwrap().keys().cloned().collect();
+    let mut keys = Overrides::KEYS.to_vec();
+    fields.sort();
+    keys.sort();
+    assert_eq!(fields, keys);
+    assert_eq!(properties["listen_port"]["default"], 21);
+    assert_eq!(properties["listen_port"]["maximum"], 65535);
+    assert_eq!(properties["local_umask"]["default"], "077");
+    assert_eq!(properties["local_umask"]["pattern"], "^0*[0-7]{1,3}$");
+    assert!("local_umask = 0022".parse::<Config>().is_ok());
+    assert_eq!(properties["listen_port"]["description"], "Control connection port");
+    assert_eq!(properties["listen_address"]["type"], "string");
+    assert_eq!(properties["idle_session_timeout"]["default"], "5m");
+    assert!(properties["listen_address"].get("default").is_none());
+    assert_eq!(schema["definitions"]["overrides"]["properties"]["max_per_ip"]["type"], "integer");
+    let pasv = &properties["pasv_enable"]["anyOf"];
+    assert_eq!(pasv[0]["type"], "boolean");
+    assert_eq!(pasv[1]["type"], "string");
+    assert!(pasv[1]["pattern"]
+      .as_str()
+      .unwrap()
+      .contains("|[yY][eE][sS]|"));
+    assert!("pasv_enable = YES".parse::<Config>().is_ok());
+    assert_eq!(properties["include"]["anyOf"][1]["items"]["type"], "string");
+  }
+
+  #[test]
+  fn layers() {
+    let dir = std::env::temp_dir().join(format!("ftpd-layers-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (base, local) = (dir.join("base.conf"), dir.join("local.conf"));
+    fs::write(&base, "listen_port = 2121\nmax_clients = 10\n[user:bob]\nmax_per_ip = 1\n").unwrap();
+    fs::write(&local, "\nmax_clients = 20\n").unwrap();
+    std::env::set_var("FTPD_IDLE_SESSION_TIMEOUT", "7m");
+    let (c, provenance) = ConfigBuilder::new()
+      .file(base.to_str().unwrap())
+      .file(local.to_str().unwrap())
+      .env()
+      .set("listen_port", "2222")
+      .build_with_provenance()
+      .unwrap();
+    std::env::remove_var("FTPD_IDLE_SESSION_TIMEOUT");
+    assert_eq!((c.listen_port, c.max_clients), (2222, 20));
+    assert_eq!(c.idle_session_timeout.as_secs(), 7 * 60);
+    assert_eq!(c.user["bob"].max_per_ip, Some(1));
+    assert_eq!(provenance.get("listen_port"), Some(&Source::Override));
+    let env = Source::Env("FTPD_IDLE_SESSION_TIMEOUT".into());
+    assert_eq!(provenance.get("idle_session_timeout"), Some(&env));
+    assert_eq!(provenance.get("pasv_enable"), Some(&Source::Default));
+    match provenance.get("max_clients") {
+      Some(Source::File { path, line }) => assert!(path.ends_with("local.conf") && *line == 2),
+      source => panic!("{:?}", source),
+    }
+
+    let e = ConfigBuilder::new()
+      .set("max_per_ip", "50")
+      .build()
+      .unwrap_err();
+    assert!(matches!(e, FtpdError::Validation(_)));
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn builder() {
+    let empty: Config = "".parse().unwrap();
+    assert_eq!(conf::to_string(&Config::default()).unwrap(), conf::to_string(&empty).unwrap());
+
+    let bob = Overrides {
+      pasv_enable: Some(false),
+      ..Overrides::default()
+    };
+    let c = Config::builder()
+      .listen_port(2121)
+      .listen_address(Some("127.0.0.1".into()))
+      .idle_session_timeout(Duration::from_secs(30))
+      .download_max_rate("2M".parse().unwrap())
+      .user("bob", bob)
+      .build()
+      .unwrap();
+    assert_eq!(c.listen_port, 2121);
+    assert_eq!(c.listen_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(c.idle_session_timeout.as_secs(), 30);
+    assert_eq!(c.download_max_rate.as_bytes(), 2 << 20);
+    assert!(
+      !c.for_session(Some("bob"), "127.0.0.1".parse().unwrap())
+        .pasv_enable
+    );
+
+    let e = Config::builder().listen_port(70000).build().unwrap_err();
+    assert!(matches!(e, FtpdError::Validation(_)));
+  }
+
+  #[test]
+  fn streaming() {
+    /// `[user:uN]` sections made up on the fly, never held as a whole
+    struct Users(std::ops::Range<u32>, Vec<u8>);
+    impl Read for Users {
+      fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if self.1.is_empty() {
+          match self.0.next() {
+            Some(n) => self.1 = format!("[user:u{}]\nmax_per_ip = {}\n", n, n % 7).into_bytes(),
+            None => return Ok(0),
+          }
+        }
+        let n = out.len().min(self.1.len());
+        out[..n].copy_from_slice(&self.1[..n]);
+        self.1.drain(..n);
+        Ok(n)
+      }
+    }
+    let mut de = conf::Deserializer::from_read(Users(0..20_000, Vec::new()))
+      .strictness(conf::Strictness::Strict);
+    let mut count = 0;
+    for section in de.sections::<Overrides>() {
+      let (name, overrides) = section.unwrap();
+      assert_eq!(name, format!("user:u{}", count));
+      assert_eq!(overrides.max_per_ip, Some(count % 7));
+      count += 1;
+    }
+    assert_eq!(count, 20_000);
+
+    let mut de = conf::Deserializer::from_str("listen_port = 21\n[user:a]\n");
+    let e = de.sections::<Overrides>().next().unwrap().unwrap_err();
+    assert!(
+      e.to_string()
+        .starts_with("line 1, column 1: `listen_port` is outside a section"),
+      "{}",
+      e
+    );
+  }
+
+  #[test]
+  fn nested() {
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    enum Auth {
+      None,
+      Password { file: String, rounds: u32 },
+    }
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    enum Compress {
+      Off,
+      Zlib(u32),
+      Window(u32, u32),
+    }
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Tls {
+      cert_file: String,
+      key_file: Option<String>,
+    }
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Server {
+      port: u32,
+      tls: Tls,
+      auth: Auth,
+      compress: Compress,
+      fallback: Vec<Compress>,
+    }
+
+    let mut de = conf::Deserializer::from_str(concat!(
+      "port = 21\n",
+      "tls.cert_file = /etc/ftpd/cert.pem\n",
+      "tls.key_file = /etc/ftpd/key.pem\n",
+      "auth.Password.file = /etc/ftpd/passwd\n",
+      "auth.Password.rounds = 10\n",
+      "compress = Zlib(6)\n",
+      "fallback = \"Window(15, 8)\", Off\n",
+    ))
+    .strictness(conf::Strictness::Strict);
+    let s: Server = serde::Deserialize::deserialize(&mut de).unwrap();
+    assert_eq!(s.tls.key_file.as_deref(), Some("/etc/ftpd/key.pem"));
+    assert_eq!(
+      s.auth,
+      Auth::Password {
+        file: "/etc/ftpd/passwd".into(),
+        rounds: 10
+      }
+    );
+    assert_eq!(s.compress, Compress::Zlib(6));
+    assert_eq!(s.fallback, [Compress::Window(15, 8), Compress::Off]);
+
+    let text = conf::to_string(&s).unwrap();
+    assert!(text.contains("auth.Password.rounds = 10\ncompress = Zlib(6)\n"));
+    assert!(text.contains("\n[tls]\ncert_file = /etc/ftpd/cert.pem\n"));
+    assert_eq!(conf::from_str::<Server>(&text).unwrap(), s);
+
+    let mut de = conf::Deserializer::from_str("tls.cert_file = a\nport = 1\ntls.key_file = b\n")
+      .strictness(conf::Strictness::Strict);
+    let e = serde::Deserialize::deserialize(&mut de)
+      .map(|_: Server| ())
+      .unwrap_err()
+      .to_string();
+    assert!(e.contains("`tls.*` keys must be kept together, the first one is on line 1"), "{}", e);
+
+    let map: std::collections::HashMap<String, String> = conf::from_str(
+      "a.b = 1
+c = 2
+",
+    )
+    .unwrap();
+    assert_eq!(map["a.b"], "1");
+    assert_eq!(map["c"], "2");
+    #[derive(Debug, Deserialize)]
+    struct Limits {
+      rate: std::collections::HashMap<String, u32>,
+    }
+    let limits: Limits = conf::from_str(
+      "rate.user.bob = 10
+rate.ip = 20
+",
+    )
+    .unwrap();
+    assert_eq!(limits.rate["user.bob"], 10);
+    assert_eq!(limits.rate["ip"], 20);
   }
 }